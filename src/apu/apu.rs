@@ -0,0 +1,301 @@
+use apu::noise::Noise;
+use apu::resampler::Resampler;
+use apu::square::Square;
+use apu::wave::Wave;
+use cpu::cpu::MASTER_CLOCK_SPEED;
+
+const NR50_ADDRESS: u16 = 0xFF24;
+const NR51_ADDRESS: u16 = 0xFF25;
+const NR52_ADDRESS: u16 = 0xFF26;
+const PCM12_ADDRESS: u16 = 0xFF76;
+const PCM34_ADDRESS: u16 = 0xFF77;
+
+// The sound generator behind 0xFF10-0xFF3F, advanced by T-cycles.
+//
+// A frame sequencer steps through 8 phases at 512 Hz, clocking length on
+// even steps, the channel 1 sweep on steps 2 and 6 and envelopes on step 7.
+// It is driven by the timer's DIV counter through step_frame_sequencer.
+pub struct Apu {
+  pub square1: Square,
+  pub square2: Square,
+  pub wave: Wave,
+  pub noise: Noise,
+  // Selects the CGB behaviour of wave RAM and power-off
+  pub cgb: bool,
+  powered: bool, // NR52 bit 7
+  volume: u8, // NR50: left volume in bits 4-6, right in bits 0-2
+  panning: u8, // NR51: channels 1-4 to the left in bits 4-7, right in 0-3
+  frame_step: u8, // The next step to run
+  // Resamples the mixed output to a host rate, once one is set
+  resampler: Option<Resampler>,
+  // One per channel when recording them in isolation
+  stems: Vec<Resampler>,
+}
+
+impl Apu {
+  pub fn new() -> Self {
+    Self {
+      square1: Square::new(true),
+      square2: Square::new(false),
+      wave: Wave::new(),
+      noise: Noise::new(),
+      cgb: false,
+      powered: true,
+      volume: 0x77,
+      panning: 0xF3,
+      frame_step: 0,
+      resampler: None,
+      stems: Vec::new(),
+    }
+  }
+
+  pub fn read(&self, address: u16) -> u8 {
+    match address {
+      0xFF10..=0xFF14 => self.square1.read(address - 0xFF10),
+      0xFF15..=0xFF19 => self.square2.read(address - 0xFF15),
+      0xFF1A..=0xFF1E => self.wave.read(address - 0xFF1A),
+      0xFF1F..=0xFF23 => self.noise.read(address - 0xFF1F),
+      NR50_ADDRESS => self.volume,
+      NR51_ADDRESS => self.panning,
+      NR52_ADDRESS => {
+        let status = self.square1.enabled as u8
+          | (self.square2.enabled as u8) << 1
+          | (self.wave.enabled as u8) << 2
+          | (self.noise.enabled as u8) << 3;
+
+        ((self.powered as u8) << 7) | 0x70 | status
+      },
+      0xFF30..=0xFF3F => self.wave.read_ram(address as usize - 0xFF30, self.cgb),
+      // CGB PCM12 and PCM34: the digital output of two channels each, the
+      // lower numbered one in the low nibble
+      PCM12_ADDRESS => self.square1.output() | (self.square2.output() << 4),
+      PCM34_ADDRESS => self.wave.output() | (self.noise.output() << 4),
+      _ => 0xFF,
+    }
+  }
+
+  pub fn write(&mut self, address: u16, value: u8) {
+    // While powered off only NR52 and wave RAM take writes, and on DMG the
+    // length counters as well
+    if !self.powered {
+      match address {
+        NR52_ADDRESS | 0xFF30..=0xFF3F => {},
+        0xFF11 | 0xFF16 | 0xFF1B | 0xFF20 if !self.cgb => {
+          self.load_length(address, value);
+          return;
+        },
+        _ => return,
+      }
+    }
+
+    // Length clocks land on even steps, so an odd next step means the
+    // current length period is in its first half
+    let extra_length_clock = self.frame_step & 0x01 == 1;
+
+    match address {
+      0xFF10..=0xFF14 => self.square1.write(address - 0xFF10, value, extra_length_clock),
+      0xFF15..=0xFF19 => self.square2.write(address - 0xFF15, value, extra_length_clock),
+      0xFF1A..=0xFF1E => self.wave.write(address - 0xFF1A, value, extra_length_clock, self.cgb),
+      0xFF1F..=0xFF23 => self.noise.write(address - 0xFF1F, value, extra_length_clock),
+      NR50_ADDRESS => self.volume = value,
+      NR51_ADDRESS => self.panning = value,
+      NR52_ADDRESS => {
+        let powered = value & 0x80 != 0;
+
+        if self.powered && !powered {
+          self.power_off();
+        } else if !self.powered && powered {
+          self.frame_step = 0;
+        }
+
+        self.powered = powered;
+      },
+      0xFF30..=0xFF3F => self.wave.write_ram(address as usize - 0xFF30, value, self.cgb),
+      _ => {},
+    }
+  }
+
+  fn load_length(&mut self, address: u16, value: u8) {
+    match address {
+      0xFF11 => self.square1.length.load(value & 0x3F),
+      0xFF16 => self.square2.length.load(value & 0x3F),
+      0xFF1B => self.wave.length.load(value),
+      0xFF20 => self.noise.length.load(value & 0x3F),
+      _ => {},
+    }
+  }
+
+  // Clears NR10-NR51. Wave RAM survives, and so do the length counters on
+  // DMG.
+  fn power_off(&mut self) {
+    let ram = self.wave.ram;
+    let lengths = [
+      self.square1.length.counter,
+      self.square2.length.counter,
+      self.wave.length.counter,
+      self.noise.length.counter,
+    ];
+
+    self.square1 = Square::new(true);
+    self.square2 = Square::new(false);
+    self.wave = Wave::new();
+    self.noise = Noise::new();
+    self.volume = 0;
+    self.panning = 0;
+
+    self.wave.ram = ram;
+    if !self.cgb {
+      self.square1.length.counter = lengths[0];
+      self.square2.length.counter = lengths[1];
+      self.wave.length.counter = lengths[2];
+      self.noise.length.counter = lengths[3];
+    }
+  }
+
+  // Advances the channels one step at a time, so that each change in the
+  // output reaches the resamplers at the exact cycle it happened on.
+  // Register writes since the last tick change the output right away.
+  pub fn tick(&mut self, cycles: u64) {
+    self.update_resamplers(0);
+    let mut remaining = cycles as u32;
+
+    while remaining > 0 {
+      let step = if self.powered { remaining.min(self.cycles_to_step()) } else { remaining };
+
+      if self.powered {
+        self.square1.tick(step);
+        self.square2.tick(step);
+        self.wave.tick(step);
+        self.noise.tick(step);
+      }
+
+      self.update_resamplers(step as u64);
+      remaining -= step;
+    }
+  }
+
+  // The fewest T-cycles until any channel steps, at least 1
+  fn cycles_to_step(&self) -> u32 {
+    self.square1.cycles_to_step()
+      .min(self.square2.cycles_to_step())
+      .min(self.wave.cycles_to_step())
+      .min(self.noise.cycles_to_step())
+      .max(1)
+  }
+
+  fn update_resamplers(&mut self, cycles: u64) {
+    if self.resampler.is_none() && self.stems.is_empty() {
+      return;
+    }
+
+    let level = self.mix();
+    if let Some(resampler) = &mut self.resampler {
+      resampler.update(cycles, level);
+    }
+
+    if !self.stems.is_empty() {
+      let levels = self.channel_mixes();
+
+      for (stem, level) in self.stems.iter_mut().zip(levels.iter()) {
+        stem.update(cycles, *level);
+      }
+    }
+  }
+
+  // Runs one step of the frame sequencer, on a falling edge of the DIV bit
+  // it follows
+  pub fn step_frame_sequencer(&mut self) {
+    if !self.powered {
+      return;
+    }
+
+    if self.frame_step & 0x01 == 0 {
+      self.square1.clock_length();
+      self.square2.clock_length();
+      self.wave.clock_length();
+      self.noise.clock_length();
+    }
+
+    if self.frame_step == 2 || self.frame_step == 6 {
+      self.square1.clock_sweep();
+    }
+
+    if self.frame_step == 7 {
+      self.square1.clock_envelope();
+      self.square2.clock_envelope();
+      self.noise.clock_envelope();
+    }
+
+    self.frame_step = (self.frame_step + 1) % 8;
+  }
+
+  // The stereo output, -1.0 to 1.0 per side. Each channel's DAC turns its
+  // 0-15 output into -1.0 to 1.0, NR51 routes it to either side and NR50
+  // scales each side by 1-8 eighths.
+  pub fn mix(&self) -> (f32, f32) {
+    self.channel_mixes().iter()
+      .fold((0.0, 0.0), |(left, right), channel| (left + channel.0, right + channel.1))
+  }
+
+  // What each channel adds to the stereo output
+  pub fn channel_mixes(&self) -> [(f32, f32); 4] {
+    if !self.powered {
+      return [(0.0, 0.0); 4];
+    }
+
+    let channels = [
+      dac(self.square1.output(), self.square1.dac_enabled()),
+      dac(self.square2.output(), self.square2.dac_enabled()),
+      dac(self.wave.output(), self.wave.dac_enabled()),
+      dac(self.noise.output(), self.noise.dac_enabled()),
+    ];
+
+    let left_volume = ((self.volume >> 4) & 0x07) as f32 + 1.0;
+    let right_volume = (self.volume & 0x07) as f32 + 1.0;
+
+    let mut mixes = [(0.0, 0.0); 4];
+    for (i, (mix, output)) in mixes.iter_mut().zip(channels.iter()).enumerate() {
+      if self.panning & (0x10 << i) != 0 {
+        mix.0 = output * left_volume / 32.0;
+      }
+      if self.panning & (0x01 << i) != 0 {
+        mix.1 = output * right_volume / 32.0;
+      }
+    }
+
+    mixes
+  }
+
+  // Starts resampling the mixed output to a host rate, e.g. 44100 or
+  // 48000 Hz. The high-pass filter follows the model picked by cgb.
+  pub fn set_sample_rate(&mut self, sample_rate: u32) {
+    self.resampler = Some(Resampler::new(MASTER_CLOCK_SPEED as u32, sample_rate, self.cgb));
+  }
+
+  // Takes the samples resampled since the last call
+  pub fn take_samples(&mut self) -> Vec<(f32, f32)> {
+    match &mut self.resampler {
+      Some(resampler) => resampler.take_samples(),
+      None => Vec::new(),
+    }
+  }
+
+  // Also resamples each channel on its own, as it would sound with the
+  // other three muted
+  pub fn enable_stems(&mut self, sample_rate: u32) {
+    self.stems = (0..4)
+      .map(|_| Resampler::new(MASTER_CLOCK_SPEED as u32, sample_rate, self.cgb))
+      .collect();
+  }
+
+  // Takes the samples of channels 1-4 resampled since the last call, none
+  // unless stems are enabled
+  pub fn take_stem_samples(&mut self) -> Vec<Vec<(f32, f32)>> {
+    self.stems.iter_mut().map(|stem| stem.take_samples()).collect()
+  }
+}
+
+// A channel DAC, silent while switched off
+fn dac(output: u8, enabled: bool) -> f32 {
+  if enabled { output as f32 / 7.5 - 1.0 } else { 0.0 }
+}
@@ -0,0 +1,100 @@
+use std::f64::consts::PI;
+
+// Sub-sample positions a step can land on, and output samples each step is
+// spread over
+const PHASES: usize = 64;
+const KERNEL_WIDTH: usize = 16;
+
+// Kernel cutoff as a fraction of the output Nyquist frequency, leaving room
+// for the transition band below it
+const CUTOFF: f64 = 0.9;
+
+// Band-limited step synthesis. The input is a level that changes at
+// T-cycle timestamps; each change is added to the output as a windowed-sinc
+// impulse and the output integrates those, so every step comes out with
+// nothing above the output Nyquist frequency to alias back down.
+pub struct BlipBuffer {
+  ratio: f64, // Output samples per input clock
+  kernel: Vec<[f32; KERNEL_WIDTH]>,
+  buffer: Vec<f32>, // Impulses, from the first sample not read yet
+  offset: f64, // Output position of the current frame's clock 0
+  integrator: f32,
+}
+
+impl BlipBuffer {
+  pub fn new(clock_rate: u32, sample_rate: u32) -> Self {
+    Self {
+      ratio: sample_rate as f64 / clock_rate as f64,
+      kernel: (0..PHASES).map(|phase| Self::kernel_phase(phase as f64 / PHASES as f64)).collect(),
+      buffer: vec![0.0; KERNEL_WIDTH],
+      offset: 0.0,
+      integrator: 0.0,
+    }
+  }
+
+  // A Blackman-windowed sinc centred a fraction of a sample into the
+  // kernel, normalised so a whole step always adds up to its delta
+  fn kernel_phase(fraction: f64) -> [f32; KERNEL_WIDTH] {
+    let half = KERNEL_WIDTH as f64 / 2.0;
+    let mut taps = [0.0; KERNEL_WIDTH];
+
+    for (k, tap) in taps.iter_mut().enumerate() {
+      let x = k as f64 - half + 1.0 - fraction;
+      let sinc = if x == 0.0 { 1.0 } else { (PI * x * CUTOFF).sin() / (PI * x * CUTOFF) };
+      let window = 0.42 + 0.5 * (2.0 * PI * x / KERNEL_WIDTH as f64).cos()
+        + 0.08 * (4.0 * PI * x / KERNEL_WIDTH as f64).cos();
+
+      *tap = sinc * window.max(0.0);
+    }
+
+    let sum: f64 = taps.iter().sum();
+    let mut kernel = [0.0; KERNEL_WIDTH];
+    for (value, tap) in kernel.iter_mut().zip(taps.iter()) {
+      *value = (tap / sum) as f32;
+    }
+
+    kernel
+  }
+
+  // Adds a change in level at a clock into the current frame
+  pub fn add_delta(&mut self, clock: u64, delta: f32) {
+    let position = self.offset + clock as f64 * self.ratio;
+    let index = position as usize;
+    let phase = ((position - index as f64) * PHASES as f64) as usize;
+
+    if self.buffer.len() < index + KERNEL_WIDTH {
+      self.buffer.resize(index + KERNEL_WIDTH, 0.0);
+    }
+
+    for (sample, tap) in self.buffer[index..].iter_mut().zip(self.kernel[phase].iter()) {
+      *sample += tap * delta;
+    }
+  }
+
+  // Ends the current frame after a number of clocks. The samples before it
+  // can then be read, since later changes can no longer reach them.
+  pub fn end_frame(&mut self, clocks: u64) {
+    self.offset += clocks as f64 * self.ratio;
+
+    let length = self.offset as usize + KERNEL_WIDTH;
+    if self.buffer.len() < length {
+      self.buffer.resize(length, 0.0);
+    }
+  }
+
+  pub fn samples_available(&self) -> usize {
+    self.offset as usize
+  }
+
+  // Appends every finished sample to the output
+  pub fn read_samples(&mut self, output: &mut Vec<f32>) {
+    let available = self.samples_available();
+
+    for impulse in self.buffer.drain(..available) {
+      self.integrator += impulse;
+      output.push(self.integrator);
+    }
+
+    self.offset -= available as f64;
+  }
+}
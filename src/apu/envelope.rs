@@ -0,0 +1,48 @@
+// Volume envelope of the square and noise channels, from NRx2: initial
+// volume in bits 4-7, direction in bit 3 (set to increase) and period in
+// bits 0-2. Clocked at 64 Hz by the frame sequencer.
+pub struct Envelope {
+  pub register: u8,
+  pub volume: u8,
+  timer: u8,
+}
+
+impl Envelope {
+  pub fn new() -> Self {
+    Self { register: 0, volume: 0, timer: 0 }
+  }
+
+  // The DAC is on while any of the upper five bits is set
+  pub fn dac_enabled(&self) -> bool {
+    self.register & 0xF8 != 0
+  }
+
+  fn period(&self) -> u8 {
+    self.register & 0x07
+  }
+
+  pub fn trigger(&mut self) {
+    self.volume = self.register >> 4;
+    self.timer = if self.period() == 0 { 8 } else { self.period() };
+  }
+
+  pub fn clock(&mut self) {
+    if self.period() == 0 {
+      return;
+    }
+
+    if self.timer > 1 {
+      self.timer -= 1;
+      return;
+    }
+    self.timer = self.period();
+
+    if self.register & 0x08 != 0 {
+      if self.volume < 15 {
+        self.volume += 1;
+      }
+    } else if self.volume > 0 {
+      self.volume -= 1;
+    }
+  }
+}
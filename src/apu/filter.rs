@@ -0,0 +1,29 @@
+// Charge kept by the output capacitor per T-cycle. The CGB one lets its
+// charge go faster.
+const DMG_CHARGE: f64 = 0.999958;
+const CGB_CHARGE: f64 = 0.998943;
+
+// The capacitor in series with each output, a high-pass filter that takes
+// the DC offset out of the DAC output so silence settles at 0
+pub struct HighPass {
+  capacitor: f32,
+  charge: f32,
+}
+
+impl HighPass {
+  pub fn new(clock_rate: u32, sample_rate: u32, cgb: bool) -> Self {
+    let charge = if cgb { CGB_CHARGE } else { DMG_CHARGE };
+
+    Self {
+      capacitor: 0.0,
+      charge: charge.powf(clock_rate as f64 / sample_rate as f64) as f32,
+    }
+  }
+
+  pub fn filter(&mut self, input: f32) -> f32 {
+    let output = input - self.capacitor;
+    self.capacitor = input - output * self.charge;
+
+    output
+  }
+}
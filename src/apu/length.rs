@@ -0,0 +1,48 @@
+// Length counter shared by all four channels, clocked at 256 Hz by the frame
+// sequencer. It switches its channel off when it reaches zero while enabled.
+pub struct LengthCounter {
+  pub enabled: bool,
+  pub counter: u16,
+  max: u16, // 64, or 256 for the wave channel
+}
+
+impl LengthCounter {
+  pub fn new(max: u16) -> Self {
+    Self { enabled: false, counter: 0, max }
+  }
+
+  // NRx1 holds the length as max minus the counter
+  pub fn load(&mut self, length: u8) {
+    self.counter = self.max - length as u16;
+  }
+
+  // Returns true when the channel has to be switched off
+  pub fn clock(&mut self) -> bool {
+    if !self.enabled || self.counter == 0 {
+      return false;
+    }
+
+    self.counter -= 1;
+    self.counter == 0
+  }
+
+  // Enabling length while the next frame sequencer step does not clock it
+  // clocks it once straight away. Returns true when that hit zero.
+  pub fn set_enabled(&mut self, enabled: bool, extra_clock: bool) -> bool {
+    let was_enabled = self.enabled;
+    self.enabled = enabled;
+
+    !was_enabled && extra_clock && self.clock()
+  }
+
+  // An expired counter is reloaded on trigger, taking the same extra clock
+  pub fn trigger(&mut self, extra_clock: bool) {
+    if self.counter == 0 {
+      self.counter = self.max;
+
+      if self.enabled && extra_clock {
+        self.counter -= 1;
+      }
+    }
+  }
+}
@@ -0,0 +1,206 @@
+use apu::apu::Apu;
+use cpu::cpu::MASTER_CLOCK_SPEED;
+
+// Ticks per quarter note. With no tempo event a file runs at 120 BPM, so
+// that makes 960 ticks a second.
+const DIVISION: u16 = 480;
+const TICKS_PER_SECOND: u64 = 960;
+
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const CONTROL_CHANGE: u8 = 0xB0;
+const EXPRESSION: u8 = 11;
+
+const TRACK_NAMES: [&str; 4] = ["Square 1", "Square 2", "Wave", "Noise"];
+
+// Square channels on MIDI channels 1 and 2, wave on 3 and noise on the
+// General MIDI percussion channel 10
+const MIDI_CHANNELS: [u8; 4] = [0, 1, 2, 9];
+
+// Percussion notes for the noise channel by how fast the LFSR runs
+const CLOSED_HI_HAT: u8 = 42;
+const SNARE: u8 = 38;
+const BASS_DRUM: u8 = 36;
+
+// What a channel is playing
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Voice {
+  note: u8,
+  volume: u8, // 0-15
+}
+
+// Turns what the four channels play into note events on four tracks of a
+// Standard MIDI File. A note starts when a channel becomes audible, is
+// retriggered or changes pitch, and ends when it falls silent. Its velocity
+// is the volume it starts at, and volume changes within it, such as
+// envelopes, become expression changes relative to that.
+pub struct MidiLog {
+  tracks: [Vec<u8>; 4],
+  last_ticks: [u64; 4], // Tick of the last event on each track
+  voices: [Option<Voice>; 4],
+  onset_volumes: [u8; 4], // Volume each note started at
+  retriggered: [bool; 4],
+  cycles: u64,
+}
+
+impl MidiLog {
+  pub fn new() -> Self {
+    Self {
+      tracks: Default::default(),
+      last_ticks: [0; 4],
+      voices: [None; 4],
+      onset_volumes: [0; 4],
+      retriggered: [false; 4],
+      cycles: 0,
+    }
+  }
+
+  // Catches retriggers of a note already playing, which polling would miss
+  pub fn write(&mut self, address: u16, value: u8) {
+    let channel = match address {
+      0xFF14 => 0,
+      0xFF19 => 1,
+      0xFF1E => 2,
+      0xFF23 => 3,
+      _ => return,
+    };
+
+    if value & 0x80 != 0 {
+      self.retriggered[channel] = true;
+    }
+  }
+
+  // Advances by T-cycles at the APU rate and looks at what the channels
+  // play now
+  pub fn tick(&mut self, cycles: u64, apu: &Apu) {
+    self.cycles += cycles;
+
+    let voices = [
+      square_voice(apu.square1.enabled && apu.square1.dac_enabled(), apu.square1.frequency, apu.square1.envelope.volume),
+      square_voice(apu.square2.enabled && apu.square2.dac_enabled(), apu.square2.frequency, apu.square2.envelope.volume),
+      wave_voice(apu),
+      noise_voice(apu),
+    ];
+
+    for (channel, voice) in voices.iter().enumerate() {
+      self.update(channel, *voice);
+    }
+  }
+
+  fn update(&mut self, channel: usize, voice: Option<Voice>) {
+    let retriggered = std::mem::replace(&mut self.retriggered[channel], false);
+    let midi_channel = MIDI_CHANNELS[channel];
+
+    match (self.voices[channel], voice) {
+      (Some(playing), Some(voice)) if playing.note == voice.note && !retriggered => {
+        if playing.volume != voice.volume {
+          let expression = (voice.volume as u32 * 127 / self.onset_volumes[channel] as u32).min(127) as u8;
+          self.event(channel, &[CONTROL_CHANGE | midi_channel, EXPRESSION, expression]);
+        }
+      },
+      (playing, voice) => {
+        if let Some(playing) = playing {
+          self.event(channel, &[NOTE_OFF | midi_channel, playing.note, 0]);
+        }
+
+        if let Some(voice) = voice {
+          self.onset_volumes[channel] = voice.volume;
+          self.event(channel, &[CONTROL_CHANGE | midi_channel, EXPRESSION, 127]);
+          self.event(channel, &[NOTE_ON | midi_channel, voice.note, velocity(voice.volume)]);
+        }
+      },
+    }
+
+    self.voices[channel] = voice;
+  }
+
+  fn event(&mut self, track: usize, event: &[u8]) {
+    let ticks = self.cycles * TICKS_PER_SECOND / MASTER_CLOCK_SPEED as u64;
+    let delta = ticks - self.last_ticks[track];
+    self.last_ticks[track] = ticks;
+
+    write_variable_length(&mut self.tracks[track], delta as u32);
+    self.tracks[track].extend_from_slice(event);
+  }
+
+  // The whole file, format 1 with a track per channel. Notes still playing
+  // are ended.
+  pub fn bytes(&mut self) -> Vec<u8> {
+    for channel in 0..4 {
+      self.update(channel, None);
+    }
+
+    let mut file = Vec::new();
+    file.extend_from_slice(b"MThd");
+    file.extend_from_slice(&6u32.to_be_bytes());
+    file.extend_from_slice(&1u16.to_be_bytes());
+    file.extend_from_slice(&(self.tracks.len() as u16).to_be_bytes());
+    file.extend_from_slice(&DIVISION.to_be_bytes());
+
+    for (name, events) in TRACK_NAMES.iter().zip(self.tracks.iter()) {
+      let mut track = vec![0x00, 0xFF, 0x03, name.len() as u8];
+      track.extend_from_slice(name.as_bytes());
+      track.extend_from_slice(events);
+      track.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+
+      file.extend_from_slice(b"MTrk");
+      file.extend_from_slice(&(track.len() as u32).to_be_bytes());
+      file.extend_from_slice(&track);
+    }
+
+    file
+  }
+}
+
+fn square_voice(playing: bool, frequency: u16, volume: u8) -> Option<Voice> {
+  if !playing || volume == 0 {
+    return None;
+  }
+
+  Some(Voice { note: note(131072.0 / (2048 - frequency as u32) as f64), volume })
+}
+
+fn wave_voice(apu: &Apu) -> Option<Voice> {
+  let wave = &apu.wave;
+  if !wave.enabled || wave.volume() == 0 {
+    return None;
+  }
+
+  Some(Voice { note: note(65536.0 / (2048 - wave.frequency as u32) as f64), volume: wave.volume() })
+}
+
+fn noise_voice(apu: &Apu) -> Option<Voice> {
+  let noise = &apu.noise;
+  if !noise.enabled || !noise.dac_enabled() || noise.envelope.volume == 0 {
+    return None;
+  }
+
+  let note = match noise.polynomial >> 4 {
+    0..=3 => CLOSED_HI_HAT,
+    4..=7 => SNARE,
+    _ => BASS_DRUM,
+  };
+
+  Some(Voice { note, volume: noise.envelope.volume })
+}
+
+// The nearest MIDI note to a frequency in Hz, A4 being note 69 at 440 Hz
+fn note(frequency: f64) -> u8 {
+  (69.0 + 12.0 * (frequency / 440.0).log2()).round().clamp(0.0, 127.0) as u8
+}
+
+fn velocity(volume: u8) -> u8 {
+  (volume as u32 * 127 / 15) as u8
+}
+
+fn write_variable_length(output: &mut Vec<u8>, value: u32) {
+  let mut bytes = vec![(value & 0x7F) as u8];
+  let mut value = value >> 7;
+
+  while value > 0 {
+    bytes.push(0x80 | (value & 0x7F) as u8);
+    value >>= 7;
+  }
+
+  output.extend(bytes.iter().rev());
+}
@@ -0,0 +1,11 @@
+pub mod apu;
+pub mod blip;
+pub mod envelope;
+pub mod filter;
+pub mod length;
+pub mod midi;
+pub mod noise;
+pub mod resampler;
+pub mod square;
+pub mod vgm;
+pub mod wave;
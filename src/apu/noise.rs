@@ -0,0 +1,131 @@
+use apu::envelope::Envelope;
+use apu::length::LengthCounter;
+
+// T-cycles per LFSR clock for the NR43 divisor codes, before the shift
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+// Channel 4, pseudo-random noise from a linear feedback shift register
+pub struct Noise {
+  pub enabled: bool,
+  pub length: LengthCounter,
+  pub envelope: Envelope,
+  // NR43: clock shift in bits 4-7, 7-bit width in bit 3, divisor in bits 0-2
+  pub polynomial: u8,
+  pub lfsr: u16,
+  timer: u32,
+}
+
+impl Noise {
+  pub fn new() -> Self {
+    Self {
+      enabled: false,
+      length: LengthCounter::new(64),
+      envelope: Envelope::new(),
+      polynomial: 0,
+      lfsr: 0x7FFF,
+      timer: DIVISORS[0],
+    }
+  }
+
+  // NR41-NR44 as offsets 1-4. Write-only bits read as 1.
+  pub fn read(&self, register: u16) -> u8 {
+    match register {
+      2 => self.envelope.register,
+      3 => self.polynomial,
+      4 => ((self.length.enabled as u8) << 6) | 0xBF,
+      _ => 0xFF,
+    }
+  }
+
+  pub fn write(&mut self, register: u16, value: u8, extra_length_clock: bool) {
+    match register {
+      1 => self.length.load(value & 0x3F),
+      2 => {
+        self.envelope.register = value;
+
+        if !self.envelope.dac_enabled() {
+          self.enabled = false;
+        }
+      },
+      3 => self.polynomial = value,
+      4 => {
+        if self.length.set_enabled(value & 0x40 != 0, extra_length_clock) {
+          self.enabled = false;
+        }
+
+        if value & 0x80 != 0 {
+          self.trigger(extra_length_clock);
+        }
+      },
+      _ => {},
+    }
+  }
+
+  fn period(&self) -> u32 {
+    DIVISORS[(self.polynomial & 0x07) as usize] << (self.polynomial >> 4)
+  }
+
+  fn trigger(&mut self, extra_length_clock: bool) {
+    self.enabled = self.envelope.dac_enabled();
+    self.timer = self.period();
+    self.lfsr = 0x7FFF;
+    self.length.trigger(extra_length_clock);
+    self.envelope.trigger();
+  }
+
+  // Advances the frequency timer by T-cycles
+  pub fn tick(&mut self, cycles: u32) {
+    let mut cycles = cycles;
+
+    while cycles >= self.timer {
+      cycles -= self.timer;
+      self.timer = self.period();
+      self.clock_lfsr();
+    }
+
+    self.timer -= cycles;
+  }
+
+  // T-cycles until the next step, the earliest the output can change
+  pub fn cycles_to_step(&self) -> u32 {
+    self.timer
+  }
+
+  // Shifts right, feeding bit 0 XOR bit 1 back into bit 14, and into bit 6
+  // as well in 7-bit mode. Shifts of 14 and 15 leave the LFSR stopped.
+  fn clock_lfsr(&mut self) {
+    if self.polynomial >> 4 >= 14 {
+      return;
+    }
+
+    let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 0x01;
+    self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+
+    if self.polynomial & 0x08 != 0 {
+      self.lfsr = (self.lfsr & !0x40) | (feedback << 6);
+    }
+  }
+
+  pub fn dac_enabled(&self) -> bool {
+    self.envelope.dac_enabled()
+  }
+
+  pub fn clock_length(&mut self) {
+    if self.length.clock() {
+      self.enabled = false;
+    }
+  }
+
+  pub fn clock_envelope(&mut self) {
+    self.envelope.clock();
+  }
+
+  // Digital output, 0-15: the envelope volume while bit 0 of the LFSR is clear
+  pub fn output(&self) -> u8 {
+    if !self.enabled || self.lfsr & 0x01 != 0 {
+      return 0;
+    }
+
+    self.envelope.volume
+  }
+}
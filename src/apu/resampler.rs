@@ -0,0 +1,57 @@
+use apu::blip::BlipBuffer;
+use apu::filter::HighPass;
+
+// Turns a stereo level changing at T-cycle timestamps into band-limited,
+// high-passed samples at a host rate
+pub struct Resampler {
+  left: BlipBuffer,
+  right: BlipBuffer,
+  left_filter: HighPass,
+  right_filter: HighPass,
+  level: (f32, f32),
+  clock: u64, // T-cycles into the current frame
+}
+
+impl Resampler {
+  pub fn new(clock_rate: u32, sample_rate: u32, cgb: bool) -> Self {
+    Self {
+      left: BlipBuffer::new(clock_rate, sample_rate),
+      right: BlipBuffer::new(clock_rate, sample_rate),
+      left_filter: HighPass::new(clock_rate, sample_rate, cgb),
+      right_filter: HighPass::new(clock_rate, sample_rate, cgb),
+      level: (0.0, 0.0),
+      clock: 0,
+    }
+  }
+
+  // Advances by T-cycles, after which the output is at a new level
+  pub fn update(&mut self, cycles: u64, level: (f32, f32)) {
+    self.clock += cycles;
+
+    if level.0 != self.level.0 {
+      self.left.add_delta(self.clock, level.0 - self.level.0);
+    }
+    if level.1 != self.level.1 {
+      self.right.add_delta(self.clock, level.1 - self.level.1);
+    }
+
+    self.level = level;
+  }
+
+  // Takes every sample finished since the last call
+  pub fn take_samples(&mut self) -> Vec<(f32, f32)> {
+    self.left.end_frame(self.clock);
+    self.right.end_frame(self.clock);
+    self.clock = 0;
+
+    let mut left = Vec::new();
+    let mut right = Vec::new();
+    self.left.read_samples(&mut left);
+    self.right.read_samples(&mut right);
+
+    left.into_iter()
+      .zip(right)
+      .map(|(left, right)| (self.left_filter.filter(left), self.right_filter.filter(right)))
+      .collect()
+  }
+}
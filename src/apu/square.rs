@@ -0,0 +1,248 @@
+use apu::envelope::Envelope;
+use apu::length::LengthCounter;
+
+// Waveforms for NRx1 bits 6-7: 12.5%, 25%, 50% and 75% duty, read from bit 7
+const DUTY_PATTERNS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+// Frequency sweep of channel 1, from NR10: period in bits 4-6, negate in
+// bit 3 and shift in bits 0-2. Clocked at 128 Hz by the frame sequencer.
+pub struct Sweep {
+  pub register: u8,
+  enabled: bool,
+  shadow: u16,
+  timer: u8,
+  // A negating calculation since the last trigger makes clearing the negate
+  // bit switch the channel off
+  negated: bool,
+}
+
+impl Sweep {
+  pub fn new() -> Self {
+    Self { register: 0, enabled: false, shadow: 0, timer: 8, negated: false }
+  }
+
+  fn period(&self) -> u8 {
+    (self.register >> 4) & 0x07
+  }
+
+  fn shift(&self) -> u8 {
+    self.register & 0x07
+  }
+
+  fn reload_timer(&mut self) {
+    self.timer = if self.period() == 0 { 8 } else { self.period() };
+  }
+
+  // The next frequency, None when it overflows 11 bits
+  fn calculate(&mut self) -> Option<u16> {
+    let delta = self.shadow >> self.shift();
+
+    let frequency = if self.register & 0x08 != 0 {
+      self.negated = true;
+      self.shadow - delta
+    } else {
+      self.shadow + delta
+    };
+
+    if frequency > 0x7FF { None } else { Some(frequency) }
+  }
+
+  // Returns false when the overflow check switches the channel off
+  fn trigger(&mut self, frequency: u16) -> bool {
+    self.shadow = frequency;
+    self.negated = false;
+    self.reload_timer();
+    self.enabled = self.period() != 0 || self.shift() != 0;
+
+    self.shift() == 0 || self.calculate().is_some()
+  }
+
+  // Returns the new frequency, or None when the channel has to be switched
+  // off, Some of the current one when nothing changes
+  fn clock(&mut self, frequency: u16) -> Option<u16> {
+    if self.timer > 1 {
+      self.timer -= 1;
+      return Some(frequency);
+    }
+    self.reload_timer();
+
+    if !self.enabled || self.period() == 0 {
+      return Some(frequency);
+    }
+
+    let next = self.calculate()?;
+    if self.shift() == 0 {
+      return Some(frequency);
+    }
+
+    self.shadow = next;
+    // The new frequency goes through the overflow check once more
+    self.calculate()?;
+
+    Some(next)
+  }
+
+  // Returns false when clearing negate after a negating calculation
+  // switches the channel off
+  fn write(&mut self, value: u8) -> bool {
+    let negate_cleared = self.register & 0x08 != 0 && value & 0x08 == 0;
+    self.register = value & 0x7F;
+
+    !(negate_cleared && self.negated)
+  }
+}
+
+// Channels 1 and 2. Only channel 1 has a sweep unit.
+pub struct Square {
+  pub enabled: bool,
+  pub sweep: Option<Sweep>,
+  pub length: LengthCounter,
+  pub envelope: Envelope,
+  duty: u8,
+  duty_position: u8,
+  pub frequency: u16,
+  timer: u32,
+}
+
+impl Square {
+  pub fn new(with_sweep: bool) -> Self {
+    Self {
+      enabled: false,
+      sweep: if with_sweep { Some(Sweep::new()) } else { None },
+      length: LengthCounter::new(64),
+      envelope: Envelope::new(),
+      duty: 0,
+      duty_position: 0,
+      frequency: 0,
+      timer: 2048 * 4,
+    }
+  }
+
+  // NRx0-NRx4 as offsets 0-4. Write-only bits read as 1.
+  pub fn read(&self, register: u16) -> u8 {
+    match register {
+      0 => match &self.sweep {
+        Some(sweep) => 0x80 | sweep.register,
+        None => 0xFF,
+      },
+      1 => (self.duty << 6) | 0x3F,
+      2 => self.envelope.register,
+      4 => ((self.length.enabled as u8) << 6) | 0xBF,
+      _ => 0xFF,
+    }
+  }
+
+  // extra_length_clock is set when the next frame sequencer step does not
+  // clock length
+  pub fn write(&mut self, register: u16, value: u8, extra_length_clock: bool) {
+    match register {
+      0 => {
+        if let Some(sweep) = &mut self.sweep {
+          if !sweep.write(value) {
+            self.enabled = false;
+          }
+        }
+      },
+      1 => {
+        self.duty = value >> 6;
+        self.length.load(value & 0x3F);
+      },
+      2 => {
+        self.envelope.register = value;
+
+        if !self.envelope.dac_enabled() {
+          self.enabled = false;
+        }
+      },
+      3 => self.frequency = (self.frequency & 0x700) | value as u16,
+      4 => {
+        self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0x07) << 8);
+
+        if self.length.set_enabled(value & 0x40 != 0, extra_length_clock) {
+          self.enabled = false;
+        }
+
+        if value & 0x80 != 0 {
+          self.trigger(extra_length_clock);
+        }
+      },
+      _ => {},
+    }
+  }
+
+  fn period(&self) -> u32 {
+    (2048 - self.frequency as u32) * 4
+  }
+
+  fn trigger(&mut self, extra_length_clock: bool) {
+    self.enabled = true;
+    self.timer = self.period();
+    self.length.trigger(extra_length_clock);
+    self.envelope.trigger();
+
+    if let Some(sweep) = &mut self.sweep {
+      if !sweep.trigger(self.frequency) {
+        self.enabled = false;
+      }
+    }
+
+    // A channel with its DAC off cannot start
+    if !self.envelope.dac_enabled() {
+      self.enabled = false;
+    }
+  }
+
+  // Advances the frequency timer by T-cycles
+  pub fn tick(&mut self, cycles: u32) {
+    let mut cycles = cycles;
+
+    while cycles >= self.timer {
+      cycles -= self.timer;
+      self.timer = self.period();
+      self.duty_position = (self.duty_position + 1) % 8;
+    }
+
+    self.timer -= cycles;
+  }
+
+  // T-cycles until the next step, the earliest the output can change
+  pub fn cycles_to_step(&self) -> u32 {
+    self.timer
+  }
+
+  pub fn dac_enabled(&self) -> bool {
+    self.envelope.dac_enabled()
+  }
+
+  pub fn clock_length(&mut self) {
+    if self.length.clock() {
+      self.enabled = false;
+    }
+  }
+
+  pub fn clock_envelope(&mut self) {
+    self.envelope.clock();
+  }
+
+  pub fn clock_sweep(&mut self) {
+    let frequency = match &mut self.sweep {
+      Some(sweep) => sweep.clock(self.frequency),
+      None => return,
+    };
+
+    match frequency {
+      Some(frequency) => self.frequency = frequency,
+      None => self.enabled = false,
+    }
+  }
+
+  // Digital output, 0-15
+  pub fn output(&self) -> u8 {
+    if !self.enabled {
+      return 0;
+    }
+
+    let high = DUTY_PATTERNS[self.duty as usize] >> (7 - self.duty_position) & 0x01;
+    high * self.envelope.volume
+  }
+}
@@ -0,0 +1,93 @@
+use cpu::cpu::MASTER_CLOCK_SPEED;
+
+// VGM files count time in samples at 44100 Hz
+const VGM_SAMPLE_RATE: u64 = 44100;
+
+// Version 1.61 is the first with the Game Boy DMG chip. Its clock sits at
+// 0x80 in the header, and the data follows the 0x100-byte header.
+const VERSION: u32 = 0x161;
+const HEADER_LENGTH: usize = 0x100;
+const DATA_OFFSET_ADDRESS: usize = 0x34;
+const DMG_CLOCK_ADDRESS: usize = 0x80;
+
+const WAIT_SAMPLES: u8 = 0x61;
+const WAIT_60TH: u8 = 0x62;
+const WAIT_50TH: u8 = 0x63;
+const WAIT_SHORT: u8 = 0x70; // Plus 0-15 for 1-16 samples
+const END_OF_DATA: u8 = 0x66;
+const DMG_WRITE: u8 = 0xB3;
+
+// Logs writes to the sound registers at 0xFF10-0xFF3F as a VGM file, for
+// playback in VGM players and for diffing APU behaviour between versions
+pub struct VgmLog {
+  commands: Vec<u8>,
+  cycles: u64, // T-cycles since the log started
+  samples: u64, // Samples of waiting written out so far
+}
+
+impl VgmLog {
+  pub fn new() -> Self {
+    Self {
+      commands: Vec::new(),
+      cycles: 0,
+      samples: 0,
+    }
+  }
+
+  // Advances by T-cycles at the APU rate
+  pub fn tick(&mut self, cycles: u64) {
+    self.cycles += cycles;
+  }
+
+  pub fn write(&mut self, address: u16, value: u8) {
+    if !(0xFF10..=0xFF3F).contains(&address) {
+      return;
+    }
+
+    self.wait_until_now();
+    self.commands.extend_from_slice(&[DMG_WRITE, (address - 0xFF10) as u8, value]);
+  }
+
+  fn wait_until_now(&mut self) {
+    let now = self.cycles * VGM_SAMPLE_RATE / MASTER_CLOCK_SPEED as u64;
+    let mut wait = now - self.samples;
+    self.samples = now;
+
+    while wait > 0 {
+      let samples = wait.min(0xFFFF);
+
+      match samples {
+        1..=16 => self.commands.push(WAIT_SHORT + (samples - 1) as u8),
+        735 => self.commands.push(WAIT_60TH),
+        882 => self.commands.push(WAIT_50TH),
+        _ => {
+          self.commands.push(WAIT_SAMPLES);
+          self.commands.extend_from_slice(&(samples as u16).to_le_bytes());
+        },
+      }
+
+      wait -= samples;
+    }
+  }
+
+  // The whole file, with the log up to now
+  pub fn bytes(&mut self) -> Vec<u8> {
+    self.wait_until_now();
+
+    let mut file = vec![0; HEADER_LENGTH];
+    file.extend_from_slice(&self.commands);
+    file.push(END_OF_DATA);
+
+    let length = file.len();
+    let mut set = |offset: usize, value: u32| file[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+
+    set(0x00, u32::from_le_bytes(*b"Vgm "));
+    set(0x04, (length - 4) as u32);
+    set(0x08, VERSION);
+    set(0x18, self.samples as u32);
+    set(DATA_OFFSET_ADDRESS, (HEADER_LENGTH - DATA_OFFSET_ADDRESS) as u32);
+    set(DMG_CLOCK_ADDRESS, MASTER_CLOCK_SPEED as u32);
+
+    file
+  }
+}
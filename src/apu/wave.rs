@@ -0,0 +1,189 @@
+use apu::length::LengthCounter;
+
+pub const WAVE_RAM_SIZE: usize = 16;
+
+// Extra T-cycles before the first sample fetch after a trigger, during which
+// the channel keeps playing the sample it last buffered
+const TRIGGER_DELAY: u32 = 6;
+
+// Channel 3, playing the 32 4-bit samples of wave RAM at 0xFF30-0xFF3F,
+// high nibble first
+pub struct Wave {
+  pub enabled: bool,
+  dac_enabled: bool,
+  pub length: LengthCounter,
+  volume_code: u8,
+  pub frequency: u16,
+  timer: u32,
+  position: u8,
+  sample_buffer: u8,
+  pub ram: [u8; WAVE_RAM_SIZE],
+  // T-cycles since the last sample fetch. DMG only lets the CPU at wave RAM
+  // while playing on the cycle the channel itself reads it.
+  since_fetch: u32,
+}
+
+impl Wave {
+  pub fn new() -> Self {
+    Self {
+      enabled: false,
+      dac_enabled: false,
+      length: LengthCounter::new(256),
+      volume_code: 0,
+      frequency: 0,
+      timer: 2048 * 2,
+      position: 0,
+      sample_buffer: 0,
+      ram: [0; WAVE_RAM_SIZE],
+      since_fetch: u32::MAX,
+    }
+  }
+
+  // NR30-NR34 as offsets 0-4. Write-only bits read as 1.
+  pub fn read(&self, register: u16) -> u8 {
+    match register {
+      0 => ((self.dac_enabled as u8) << 7) | 0x7F,
+      2 => (self.volume_code << 5) | 0x9F,
+      4 => ((self.length.enabled as u8) << 6) | 0xBF,
+      _ => 0xFF,
+    }
+  }
+
+  pub fn write(&mut self, register: u16, value: u8, extra_length_clock: bool, cgb: bool) {
+    match register {
+      0 => {
+        self.dac_enabled = value & 0x80 != 0;
+
+        if !self.dac_enabled {
+          self.enabled = false;
+        }
+      },
+      1 => self.length.load(value),
+      2 => self.volume_code = (value >> 5) & 0x03,
+      3 => self.frequency = (self.frequency & 0x700) | value as u16,
+      4 => {
+        self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0x07) << 8);
+
+        if self.length.set_enabled(value & 0x40 != 0, extra_length_clock) {
+          self.enabled = false;
+        }
+
+        if value & 0x80 != 0 {
+          self.trigger(extra_length_clock, cgb);
+        }
+      },
+      _ => {},
+    }
+  }
+
+  // While playing, wave RAM accesses hit the byte being played. DMG only
+  // allows that on the cycle it was fetched, reading 0xFF otherwise.
+  pub fn read_ram(&self, offset: usize, cgb: bool) -> u8 {
+    if !self.enabled {
+      return self.ram[offset];
+    }
+
+    if cgb || self.fetched_this_cycle() {
+      self.ram[self.position as usize / 2]
+    } else {
+      0xFF
+    }
+  }
+
+  pub fn write_ram(&mut self, offset: usize, value: u8, cgb: bool) {
+    if !self.enabled {
+      self.ram[offset] = value;
+    } else if cgb || self.fetched_this_cycle() {
+      self.ram[self.position as usize / 2] = value;
+    }
+  }
+
+  fn fetched_this_cycle(&self) -> bool {
+    self.since_fetch < 2
+  }
+
+  fn period(&self) -> u32 {
+    (2048 - self.frequency as u32) * 2
+  }
+
+  fn trigger(&mut self, extra_length_clock: bool, cgb: bool) {
+    // Retriggering on DMG just as a sample is fetched corrupts the first
+    // bytes of wave RAM with the ones about to be played
+    if !cgb && self.enabled && self.timer <= 2 {
+      let next = ((self.position as usize + 1) % 32) / 2;
+
+      if next < 4 {
+        self.ram[0] = self.ram[next];
+      } else {
+        let block = next & !0x03;
+        let (head, tail) = self.ram.split_at_mut(block);
+        head[..4].copy_from_slice(&tail[..4]);
+      }
+    }
+
+    self.enabled = self.dac_enabled;
+    self.timer = self.period() + TRIGGER_DELAY;
+    self.position = 0;
+    self.length.trigger(extra_length_clock);
+  }
+
+  // Advances the frequency timer by T-cycles
+  pub fn tick(&mut self, cycles: u32) {
+    let mut cycles = cycles;
+    self.since_fetch = self.since_fetch.saturating_add(cycles);
+
+    while cycles >= self.timer {
+      cycles -= self.timer;
+      self.timer = self.period();
+
+      if self.enabled {
+        self.position = (self.position + 1) % 32;
+        self.sample_buffer = self.ram[self.position as usize / 2];
+        self.since_fetch = cycles;
+      }
+    }
+
+    self.timer -= cycles;
+  }
+
+  // T-cycles until the next step, the earliest the output can change
+  pub fn cycles_to_step(&self) -> u32 {
+    self.timer
+  }
+
+  pub fn dac_enabled(&self) -> bool {
+    self.dac_enabled
+  }
+
+  pub fn clock_length(&mut self) {
+    if self.length.clock() {
+      self.enabled = false;
+    }
+  }
+
+  // NR32 as a 0-15 volume: muted, 100%, 50% or 25%
+  pub fn volume(&self) -> u8 {
+    match self.volume_code {
+      0 => 0,
+      code => 15 >> (code - 1),
+    }
+  }
+
+  // Digital output, 0-15, after the NR32 volume shift
+  pub fn output(&self) -> u8 {
+    if !self.enabled {
+      return 0;
+    }
+
+    let sample = if self.position & 0x01 == 0 {
+      self.sample_buffer >> 4
+    } else {
+      self.sample_buffer & 0x0F
+    };
+
+    match self.volume_code {
+      0 => 0,
+      code => sample >> (code - 1),
+    }
+  }
+}
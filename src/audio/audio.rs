@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+use std::io::Result;
+
+// Somewhere resampled stereo audio goes, one (left, right) pair of -1.0 to
+// 1.0 per sample. Lets headless runs and tests take the APU output without
+// a sound card.
+pub trait AudioSink {
+  fn write(&mut self, samples: &[(f32, f32)]) -> Result<()>;
+
+  // Called once the run is over, for sinks that have to finish a file
+  fn finish(&mut self) -> Result<()> {
+    Ok(())
+  }
+}
+
+// Throws everything away
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+  fn write(&mut self, _samples: &[(f32, f32)]) -> Result<()> {
+    Ok(())
+  }
+}
+
+// Keeps the latest samples up to a capacity, dropping the oldest once full,
+// for a consumer that reads at its own pace
+pub struct RingBufferSink {
+  samples: VecDeque<(f32, f32)>,
+  capacity: usize,
+}
+
+impl RingBufferSink {
+  pub fn new(capacity: usize) -> Self {
+    Self {
+      samples: VecDeque::with_capacity(capacity),
+      capacity,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.samples.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.samples.is_empty()
+  }
+
+  // Moves up to output.len() of the oldest samples into output, returning
+  // how many it did
+  pub fn read(&mut self, output: &mut [(f32, f32)]) -> usize {
+    let count = output.len().min(self.samples.len());
+
+    for (slot, sample) in output.iter_mut().zip(self.samples.drain(..count)) {
+      *slot = sample;
+    }
+
+    count
+  }
+}
+
+impl AudioSink for RingBufferSink {
+  fn write(&mut self, samples: &[(f32, f32)]) -> Result<()> {
+    for &sample in samples {
+      if self.samples.len() == self.capacity {
+        self.samples.pop_front();
+      }
+
+      self.samples.push_back(sample);
+    }
+
+    Ok(())
+  }
+}
@@ -0,0 +1,95 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Result;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use audio::audio::AudioSink;
+
+const HEADER_LENGTH: u32 = 44;
+const BITS_PER_SAMPLE: u16 = 16;
+
+// 16-bit PCM WAV output, stereo or mono. The sizes in the header are only
+// known at the end, so they are patched in by finish.
+pub struct WavSink<W: Write + Seek> {
+  writer: W,
+  sample_rate: u32,
+  channels: u16,
+  data_length: u32, // Bytes of sample data written
+}
+
+impl WavSink<BufWriter<File>> {
+  pub fn create(path: &Path, sample_rate: u32, channels: u16) -> Result<Self> {
+    Self::new(BufWriter::new(File::create(path)?), sample_rate, channels)
+  }
+}
+
+impl<W: Write + Seek> WavSink<W> {
+  pub fn new(writer: W, sample_rate: u32, channels: u16) -> Result<Self> {
+    let mut sink = Self { writer, sample_rate, channels, data_length: 0 };
+    sink.write_header()?;
+
+    Ok(sink)
+  }
+
+  fn write_header(&mut self) -> Result<()> {
+    let block_align = self.channels * BITS_PER_SAMPLE / 8;
+
+    let mut header = Vec::with_capacity(HEADER_LENGTH as usize);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(HEADER_LENGTH - 8 + self.data_length).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    header.extend_from_slice(&self.channels.to_le_bytes());
+    header.extend_from_slice(&self.sample_rate.to_le_bytes());
+    header.extend_from_slice(&(self.sample_rate * block_align as u32).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&self.data_length.to_le_bytes());
+
+    self.writer.write_all(&header)
+  }
+}
+
+impl<W: Write + Seek> AudioSink for WavSink<W> {
+  // Mono files get the average of both sides
+  fn write(&mut self, samples: &[(f32, f32)]) -> Result<()> {
+    let mut data = Vec::with_capacity(samples.len() * self.channels as usize * 2);
+
+    for &(left, right) in samples {
+      if self.channels == 1 {
+        data.extend_from_slice(&to_pcm((left + right) / 2.0).to_le_bytes());
+      } else {
+        data.extend_from_slice(&to_pcm(left).to_le_bytes());
+        data.extend_from_slice(&to_pcm(right).to_le_bytes());
+      }
+    }
+
+    self.data_length += data.len() as u32;
+    self.writer.write_all(&data)
+  }
+
+  fn finish(&mut self) -> Result<()> {
+    self.writer.seek(SeekFrom::Start(0))?;
+    self.write_header()?;
+    self.writer.seek(SeekFrom::End(0))?;
+
+    self.writer.flush()
+  }
+}
+
+fn to_pcm(sample: f32) -> i16 {
+  (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+// Where the recording of one channel goes next to the mixed one, e.g.
+// song.ch1.wav for channel 1 of song.wav
+pub fn stem_path(path: &Path, channel: usize) -> PathBuf {
+  let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+  path.with_file_name(format!("{}.ch{}.wav", stem, channel))
+}
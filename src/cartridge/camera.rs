@@ -0,0 +1,213 @@
+use cartridge::camera_sensor::CameraSensor;
+use cartridge::camera_sensor::TestPattern;
+use cartridge::camera_sensor::SENSOR_HEIGHT;
+use cartridge::camera_sensor::SENSOR_WIDTH;
+use cartridge::mbc::read_rom_bank;
+use cartridge::mbc::Mbc;
+use cartridge::mbc::RAM_BANK_SIZE;
+
+const RAM_SIZE: usize = 0x20000;
+const REGISTER_COUNT: usize = 0x36;
+const IMAGE_OFFSET: usize = 0x0100; // Captured tiles, in RAM bank 0
+
+// Sensor registers, A000-A035
+const CONTROL: usize = 0x00;
+const GAIN_AND_EDGE_MODE: usize = 0x01;
+const EXPOSURE_HIGH: usize = 0x02;
+const EXPOSURE_LOW: usize = 0x03;
+const EDGE_RATIO_AND_INVERT: usize = 0x04;
+const DITHER_MATRIX: usize = 0x06;
+
+const EDGE_RATIOS: [f32; 8] = [0.5, 0.75, 1.0, 1.25, 2.0, 3.0, 4.0, 5.0];
+
+pub struct PocketCamera {
+  rom_bank: usize,
+  ram_bank: usize,
+  registers_selected: bool,
+  ram_write_enabled: bool,
+  ram: Vec<u8>,
+  registers: [u8; REGISTER_COUNT],
+  capture_cycles: u64,
+  sensor: Box<dyn CameraSensor>,
+}
+
+impl PocketCamera {
+  pub fn new() -> Self {
+    Self {
+      rom_bank: 1,
+      ram_bank: 0,
+      registers_selected: false,
+      ram_write_enabled: false,
+      ram: vec![0; RAM_SIZE],
+      registers: [0; REGISTER_COUNT],
+      capture_cycles: 0,
+      sensor: Box::new(TestPattern),
+    }
+  }
+
+  fn exposure(&self) -> u32 {
+    ((self.registers[EXPOSURE_HIGH] as u32) << 8) | self.registers[EXPOSURE_LOW] as u32
+  }
+
+  // In T-cycles: 32446 M-cycles, 512 more without the N bit, 16 per exposure step
+  fn capture_length(&self) -> u64 {
+    let n_penalty = if self.registers[GAIN_AND_EDGE_MODE] & 0x80 == 0 { 512 } else { 0 };
+
+    (32446 + n_penalty + 16 * self.exposure() as u64) * 4
+  }
+
+  fn start_capture(&mut self) {
+    self.registers[CONTROL] |= 0x01;
+    self.capture_cycles = self.capture_length();
+  }
+
+  // Exposure scales the sensor output, edge enhancement sharpens it against
+  // its four neighbours, and the dither matrix quantises it to 2 bits
+  fn finish_capture(&mut self) {
+    self.registers[CONTROL] &= !0x01;
+
+    let pixels = self.sensor.capture();
+    let exposure = self.exposure() as f32 / 0x1000 as f32;
+    let invert = self.registers[EDGE_RATIO_AND_INVERT] & 0x08 != 0;
+    let edge_ratio = EDGE_RATIOS[((self.registers[EDGE_RATIO_AND_INVERT] >> 4) & 0x07) as usize];
+    let enhance_edges = self.registers[GAIN_AND_EDGE_MODE] & 0xE0 == 0xE0;
+
+    let sample = |x: isize, y: isize| -> f32 {
+      let x = x.clamp(0, SENSOR_WIDTH as isize - 1) as usize;
+      let y = y.clamp(0, SENSOR_HEIGHT as isize - 1) as usize;
+      let value = pixels.get(y * SENSOR_WIDTH + x).copied().unwrap_or(0) as f32 * exposure;
+
+      if invert { 255.0 - value.min(255.0) } else { value }
+    };
+
+    for y in 0..SENSOR_HEIGHT {
+      for x in 0..SENSOR_WIDTH {
+        let (sx, sy) = (x as isize, y as isize);
+        let mut value = sample(sx, sy);
+
+        if enhance_edges {
+          let neighbours = sample(sx - 1, sy) + sample(sx + 1, sy) + sample(sx, sy - 1) + sample(sx, sy + 1);
+          value += (value * 4.0 - neighbours) * edge_ratio;
+        }
+
+        let value = value.clamp(0.0, 255.0) as u8;
+        let matrix = DITHER_MATRIX + ((y % 4) * 4 + (x % 4)) * 3;
+        let thresholds = &self.registers[matrix..matrix + 3];
+
+        let colour = if value < thresholds[0] {
+          3
+        } else if value < thresholds[1] {
+          2
+        } else if value < thresholds[2] {
+          1
+        } else {
+          0
+        };
+
+        self.set_image_pixel(x, y, colour);
+      }
+    }
+  }
+
+  fn set_image_pixel(&mut self, x: usize, y: usize, colour: u8) {
+    let tile = (y / 8) * (SENSOR_WIDTH / 8) + x / 8;
+    let offset = IMAGE_OFFSET + tile * 16 + (y % 8) * 2;
+    let bit = 7 - (x % 8);
+
+    self.ram[offset] = (self.ram[offset] & !(1 << bit)) | ((colour & 0x01) << bit);
+    self.ram[offset + 1] = (self.ram[offset + 1] & !(1 << bit)) | (((colour >> 1) & 0x01) << bit);
+  }
+}
+
+impl Mbc for PocketCamera {
+  fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+    match address {
+      0x0000..=0x3FFF => read_rom_bank(rom, 0, address),
+      _ => read_rom_bank(rom, self.rom_bank, address),
+    }
+  }
+
+  fn write_rom(&mut self, address: u16, value: u8) {
+    match address {
+      0x0000..=0x1FFF => self.ram_write_enabled = value & 0x0F == 0x0A,
+      0x2000..=0x3FFF => self.rom_bank = (value & 0x3F) as usize,
+      0x4000..=0x5FFF => {
+        self.registers_selected = value & 0x10 != 0;
+        self.ram_bank = (value & 0x0F) as usize;
+      },
+      _ => {},
+    }
+  }
+
+  fn read_ram(&self, address: u16) -> u8 {
+    if self.registers_selected {
+      // Only the control register can be read back
+      return match (address as usize - 0xA000) & 0x7F {
+        CONTROL => self.registers[CONTROL] & 0x07,
+        _ => 0x00,
+      };
+    }
+
+    // The sensor holds the bus while capturing
+    if self.capture_cycles > 0 {
+      return 0x00;
+    }
+
+    self.ram[self.ram_bank * RAM_BANK_SIZE + (address as usize - 0xA000)]
+  }
+
+  fn write_ram(&mut self, address: u16, value: u8) -> bool {
+    if self.registers_selected {
+      let register = (address as usize - 0xA000) & 0x7F;
+
+      match register {
+        CONTROL => {
+          self.registers[CONTROL] = value & 0x06;
+
+          if value & 0x01 != 0 {
+            self.start_capture();
+          } else {
+            self.capture_cycles = 0;
+          }
+        },
+        r if r < REGISTER_COUNT => self.registers[r] = value,
+        _ => {},
+      }
+      return false;
+    }
+
+    if self.ram_write_enabled && self.capture_cycles == 0 {
+      self.ram[self.ram_bank * RAM_BANK_SIZE + (address as usize - 0xA000)] = value;
+      return true;
+    }
+
+    false
+  }
+
+  fn tick(&mut self, cycles: u64) -> bool {
+    if self.capture_cycles == 0 {
+      return false;
+    }
+
+    if cycles >= self.capture_cycles {
+      self.capture_cycles = 0;
+      self.finish_capture();
+      true
+    } else {
+      self.capture_cycles -= cycles;
+      false
+    }
+  }
+
+  fn ram(&self) -> &[u8] {
+    &self.ram
+  }
+
+  fn ram_mut(&mut self) -> &mut [u8] {
+    &mut self.ram
+  }
+
+  fn set_camera_sensor(&mut self, sensor: Box<dyn CameraSensor>) {
+    self.sensor = sensor;
+  }
+}
@@ -0,0 +1,173 @@
+use std::fs;
+use std::fs::File;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+use std::path::Path;
+
+use png;
+
+pub const SENSOR_WIDTH: usize = 128;
+pub const SENSOR_HEIGHT: usize = 112;
+
+// Something the Pocket Camera can point at. Pixels are 8-bit grey levels,
+// 0 being black, row by row.
+pub trait CameraSensor {
+  fn capture(&mut self) -> Vec<u8>;
+}
+
+// Diagonal gradient with a checkerboard in one corner, enough contrast to
+// exercise the dithering and edge enhancement without any input file
+pub struct TestPattern;
+
+impl CameraSensor for TestPattern {
+  fn capture(&mut self) -> Vec<u8> {
+    let mut pixels = vec![0; SENSOR_WIDTH * SENSOR_HEIGHT];
+
+    for y in 0..SENSOR_HEIGHT {
+      for x in 0..SENSOR_WIDTH {
+        pixels[y * SENSOR_WIDTH + x] = if x < 32 && y < 32 {
+          if (x / 8 + y / 8) % 2 == 0 { 0xFF } else { 0x00 }
+        } else {
+          ((x + y) * 0xFF / (SENSOR_WIDTH + SENSOR_HEIGHT - 2)) as u8
+        };
+      }
+    }
+
+    pixels
+  }
+}
+
+// A still picture, loaded once and scaled to the sensor resolution
+pub struct StillImage {
+  pixels: Vec<u8>,
+}
+
+impl StillImage {
+  pub fn from_file(path: &Path) -> Result<Self> {
+    let extension = path.extension()
+      .and_then(|extension| extension.to_str())
+      .unwrap_or("")
+      .to_lowercase();
+
+    let (width, height, grey) = match extension.as_str() {
+      "png" => Self::decode_png(path)?,
+      "pgm" => Self::decode_pgm(&fs::read(path)?)?,
+      _ => return Err(Error::new(ErrorKind::InvalidInput, "Camera images must be PNG or PGM files")),
+    };
+
+    Ok(Self::from_grey(width, height, &grey))
+  }
+
+  pub fn from_grey(width: usize, height: usize, grey: &[u8]) -> Self {
+    let mut pixels = vec![0; SENSOR_WIDTH * SENSOR_HEIGHT];
+
+    if width > 0 && height > 0 {
+      for y in 0..SENSOR_HEIGHT {
+        for x in 0..SENSOR_WIDTH {
+          let source_x = x * width / SENSOR_WIDTH;
+          let source_y = y * height / SENSOR_HEIGHT;
+          pixels[y * SENSOR_WIDTH + x] = grey[source_y * width + source_x];
+        }
+      }
+    }
+
+    Self { pixels }
+  }
+
+  fn decode_png(path: &Path) -> Result<(usize, usize, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+    let mut reader = decoder.read_info()
+      .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)
+      .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+    let channels = info.color_type.samples();
+    let grey = buffer[..info.buffer_size()]
+      .chunks(channels)
+      .map(|pixel| {
+        if channels >= 3 {
+          ((pixel[0] as u32 * 299 + pixel[1] as u32 * 587 + pixel[2] as u32 * 114) / 1000) as u8
+        } else {
+          pixel[0]
+        }
+      })
+      .collect();
+
+    Ok((info.width as usize, info.height as usize, grey))
+  }
+
+  // Binary (P5) and plain (P2) greymaps
+  fn decode_pgm(data: &[u8]) -> Result<(usize, usize, Vec<u8>)> {
+    let invalid = || Error::new(ErrorKind::InvalidData, "Malformed PGM file");
+
+    let mut position = 0;
+    let mut header = Vec::new();
+
+    while header.len() < 4 {
+      while position < data.len() && data[position].is_ascii_whitespace() {
+        position += 1;
+      }
+
+      if position < data.len() && data[position] == b'#' {
+        while position < data.len() && data[position] != b'\n' {
+          position += 1;
+        }
+        continue;
+      }
+
+      let start = position;
+      while position < data.len() && !data[position].is_ascii_whitespace() {
+        position += 1;
+      }
+
+      if start == position {
+        return Err(invalid());
+      }
+
+      header.push(String::from_utf8_lossy(&data[start..position]).into_owned());
+    }
+
+    let width: usize = header[1].parse().map_err(|_| invalid())?;
+    let height: usize = header[2].parse().map_err(|_| invalid())?;
+    let max_value: usize = header[3].parse().map_err(|_| invalid())?;
+
+    if max_value == 0 || max_value > 0xFF {
+      return Err(Error::new(ErrorKind::InvalidData, "Only 8-bit PGM files are supported"));
+    }
+
+    let size = width.checked_mul(height).ok_or_else(invalid)?;
+
+    let samples: Vec<usize> = match header[0].as_str() {
+      "P5" => data.get(position + 1..)
+        .and_then(|pixels| pixels.get(..size))
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|&sample| sample as usize)
+        .collect(),
+      "P2" => String::from_utf8_lossy(&data[position..])
+        .split_whitespace()
+        .take(size)
+        .map(|sample| sample.parse().map_err(|_| invalid()))
+        .collect::<Result<_>>()?,
+      _ => return Err(invalid()),
+    };
+
+    if samples.len() != size {
+      return Err(invalid());
+    }
+
+    let grey = samples.iter().map(|&sample| (sample.min(max_value) * 0xFF / max_value) as u8).collect();
+
+    Ok((width, height, grey))
+  }
+}
+
+impl CameraSensor for StillImage {
+  fn capture(&mut self) -> Vec<u8> {
+    self.pixels.clone()
+  }
+}
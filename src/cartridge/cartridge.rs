@@ -0,0 +1,259 @@
+use std::fs;
+use std::io::Result;
+use std::path::Path;
+use std::path::PathBuf;
+
+use cartridge::camera::PocketCamera;
+use cartridge::camera_sensor::CameraSensor;
+use cartridge::huc1::Huc1;
+use cartridge::huc3::Huc3;
+use cartridge::mbc::Mbc;
+use cartridge::mbc::RomOnly;
+use cartridge::mbc::RomRam;
+use cartridge::mbc1::Mbc1;
+use cartridge::mbc2::Mbc2;
+use cartridge::mbc3::Mbc3;
+use cartridge::mbc5::Mbc5;
+use cartridge::mbc7::Mbc7;
+use cartridge::save;
+use infrared::infrared::InfraredLink;
+
+const TITLE_ADDRESS: usize = 0x0134;
+const TITLE_LENGTH: usize = 16;
+const CGB_FLAG_ADDRESS: usize = 0x0143;
+const CARTRIDGE_TYPE_ADDRESS: usize = 0x0147;
+const RAM_SIZE_ADDRESS: usize = 0x0149;
+
+fn ram_size(code: u8) -> usize {
+  match code {
+    0x02 => 0x2000,
+    0x03 => 0x8000,
+    0x04 => 0x20000,
+    0x05 => 0x10000,
+    _ => 0,
+  }
+}
+
+pub struct Cartridge {
+  pub title: String,
+  // Set for CGB-enhanced and CGB-only games, which run in colour mode
+  pub cgb: bool,
+  rom: Vec<u8>,
+  mbc: Box<dyn Mbc>,
+  // Battery files are named after this, the ROM unless asked otherwise
+  save_base: Option<PathBuf>,
+  battery: bool,
+  dirty: bool,
+}
+
+impl Cartridge {
+  pub fn new(rom: Vec<u8>) -> Self {
+    let cartridge_type = rom.get(CARTRIDGE_TYPE_ADDRESS).copied().unwrap_or(0x00);
+
+    let title = rom.get(TITLE_ADDRESS..TITLE_ADDRESS + TITLE_LENGTH)
+      .unwrap_or(&[])
+      .iter()
+      .take_while(|&&c| c != 0)
+      .map(|&c| c as char)
+      .collect();
+
+    let cgb = rom.get(CGB_FLAG_ADDRESS).is_some_and(|flag| flag & 0x80 != 0);
+    let ram_size = ram_size(rom.get(RAM_SIZE_ADDRESS).copied().unwrap_or(0x00));
+
+    let mbc: Box<dyn Mbc> = match cartridge_type {
+      0x01..=0x03 => Box::new(Mbc1::new(ram_size)),
+      0x05 | 0x06 => Box::new(Mbc2::new()),
+      0x08 | 0x09 => Box::new(RomRam::new(ram_size)),
+      0x0F | 0x10 => Box::new(Mbc3::new(ram_size, true)),
+      0x11..=0x13 => Box::new(Mbc3::new(ram_size, false)),
+      0x19..=0x1B => Box::new(Mbc5::new(ram_size, false)),
+      0x1C..=0x1E => Box::new(Mbc5::new(ram_size, true)),
+      0x22 => Box::new(Mbc7::new()),
+      0xFC => Box::new(PocketCamera::new()),
+      // HuC3 carts report no RAM in the header but carry 32 KiB
+      0xFE => Box::new(Huc3::new(ram_size.max(0x8000))),
+      0xFF => Box::new(Huc1::new(ram_size)),
+      _ => Box::new(RomOnly),
+    };
+
+    Self {
+      title,
+      cgb,
+      rom,
+      mbc,
+      save_base: None,
+      battery: save::has_battery(cartridge_type),
+      dirty: false,
+    }
+  }
+
+  // A ROM with a mapper picked by the caller rather than the header, for
+  // images that are not cartridge dumps
+  pub fn with_mbc(title: String, rom: Vec<u8>, mbc: Box<dyn Mbc>) -> Self {
+    Self {
+      title,
+      cgb: false,
+      rom,
+      mbc,
+      save_base: None,
+      battery: false,
+      dirty: false,
+    }
+  }
+
+  pub fn from_file(path: &Path) -> Result<Self> {
+    Self::from_file_saving_to(path, path)
+  }
+
+  // Loads a ROM whose .sav and .eep sit next to save_base instead, so that
+  // two cartridges from the same ROM keep apart
+  pub fn from_file_saving_to(path: &Path, save_base: &Path) -> Result<Self> {
+    let rom = fs::read(path)?;
+
+    let mut cartridge = Self::new(rom);
+    cartridge.save_base = Some(save_base.to_path_buf());
+    cartridge.load_eeprom()?;
+    cartridge.load_ram()?;
+
+    Ok(cartridge)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.rom.is_empty()
+  }
+
+  pub fn read(&self, address: u16) -> u8 {
+    match address {
+      0x0000..=0x7FFF => self.mbc.read_rom(&self.rom, address),
+      0xA000..=0xBFFF => self.mbc.read_ram(address),
+      _ => 0xFF,
+    }
+  }
+
+  pub fn write(&mut self, address: u16, value: u8) {
+    match address {
+      0x0000..=0x7FFF => self.mbc.write_rom(address, value),
+      0xA000..=0xBFFF => self.dirty |= self.mbc.write_ram(address, value),
+      _ => {},
+    }
+  }
+
+  pub fn tick(&mut self, cycles: u64) {
+    self.dirty |= self.mbc.tick(cycles);
+  }
+
+  pub fn set_tilt(&mut self, x: f32, y: f32) {
+    self.mbc.set_tilt(x, y);
+  }
+
+  pub fn connect_infrared(&mut self, link: Box<dyn InfraredLink>) {
+    if let Some(infrared) = self.mbc.infrared_mut() {
+      infrared.connect(link);
+    }
+  }
+
+  pub fn set_camera_sensor(&mut self, sensor: Box<dyn CameraSensor>) {
+    self.mbc.set_camera_sensor(sensor);
+  }
+
+  pub fn take_speaker_tone(&mut self) -> Option<u8> {
+    self.mbc.take_speaker_tone()
+  }
+
+  // Writes everything the cartridge keeps across power cycles
+  pub fn save(&mut self) -> Result<()> {
+    if !self.battery {
+      return Ok(());
+    }
+
+    self.save_eeprom()?;
+    self.save_ram()?;
+    self.dirty = false;
+
+    Ok(())
+  }
+
+  // Saves only if the game touched cartridge RAM since the last save
+  pub fn flush(&mut self) -> Result<()> {
+    if self.dirty {
+      self.save()
+    } else {
+      Ok(())
+    }
+  }
+
+  fn save_path(&self) -> Option<PathBuf> {
+    self.save_base.as_ref().map(|path| path.with_extension("sav"))
+  }
+
+  fn load_ram(&mut self) -> Result<()> {
+    let data = match self.save_path() {
+      Some(path) if self.battery => save::read_if_exists(&path)?,
+      _ => None,
+    };
+
+    if let Some(data) = data {
+      // Saves from tools that pad or trim the RAM still load
+      let ram = self.mbc.ram_mut();
+      let length = data.len().min(ram.len());
+      ram[..length].copy_from_slice(&data[..length]);
+
+      if data.len() > ram.len() {
+        self.mbc.load_rtc_state(&data[length..]);
+      }
+    }
+
+    Ok(())
+  }
+
+  fn save_ram(&mut self) -> Result<()> {
+    let path = match self.save_path() {
+      Some(path) => path,
+      None => return Ok(()),
+    };
+
+    let mut data = self.mbc.ram().to_vec();
+    if let Some(rtc_state) = self.mbc.rtc_state() {
+      data.extend_from_slice(&rtc_state);
+    }
+
+    if data.is_empty() {
+      return Ok(());
+    }
+
+    save::write_atomically(&path, &data)
+  }
+
+  fn eeprom_path(&self) -> Option<PathBuf> {
+    self.save_base.as_ref().map(|path| path.with_extension("eep"))
+  }
+
+  fn load_eeprom(&mut self) -> Result<()> {
+    let data = match self.eeprom_path() {
+      Some(path) => save::read_if_exists(&path)?,
+      None => None,
+    };
+
+    if let (Some(eeprom), Some(data)) = (self.mbc.eeprom_mut(), data) {
+      eeprom.load(&data);
+    }
+
+    Ok(())
+  }
+
+  fn save_eeprom(&self) -> Result<()> {
+    match (self.mbc.eeprom(), self.eeprom_path()) {
+      (Some(eeprom), Some(path)) => save::write_atomically(&path, &eeprom.data),
+      _ => Ok(()),
+    }
+  }
+}
+
+impl Drop for Cartridge {
+  // Last chance to keep the player's progress, also on panics
+  fn drop(&mut self) {
+    if let Err(e) = self.flush() {
+      println!("Error saving cartridge data: {}", e);
+    }
+  }
+}
@@ -0,0 +1,180 @@
+// 93LC56 serial EEPROM: 128 words of 16 bits, driven bit by bit through
+// the CS, CLK and DI lines and answering on DO.
+
+pub const EEPROM_SIZE: usize = 256; // bytes
+const WORD_COUNT: usize = EEPROM_SIZE / 2;
+const COMMAND_LENGTH: u8 = 10; // 2 opcode bits followed by 8 address bits
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+  Idle,
+  Command { bits: u16, count: u8 },
+  Read { address: usize, data: u16, count: u8 },
+  Write { address: Option<usize>, data: u16, count: u8 },
+}
+
+pub struct Eeprom {
+  pub data: [u8; EEPROM_SIZE],
+  state: State,
+  write_enabled: bool,
+  cs: bool,
+  clk: bool,
+  di: bool,
+  do_: bool,
+  written: bool, // Since the last pin write
+}
+
+impl Eeprom {
+  pub fn new() -> Self {
+    Self {
+      data: [0xFF; EEPROM_SIZE],
+      state: State::Idle,
+      write_enabled: false,
+      cs: false,
+      clk: false,
+      di: false,
+      do_: true,
+      written: false,
+    }
+  }
+
+  pub fn load(&mut self, data: &[u8]) {
+    let length = data.len().min(EEPROM_SIZE);
+    self.data[..length].copy_from_slice(&data[..length]);
+  }
+
+  // Bit 7: CS, bit 6: CLK, bit 1: DI, bit 0: DO
+  pub fn read_pins(&self) -> u8 {
+    ((self.cs as u8) << 7) | ((self.clk as u8) << 6) | ((self.di as u8) << 1) | (self.do_ as u8)
+  }
+
+  // Returns whether the pins completed a write or erase
+  pub fn write_pins(&mut self, value: u8) -> bool {
+    let cs = value & 0x80 != 0;
+    let clk = value & 0x40 != 0;
+    self.di = value & 0x02 != 0;
+
+    if !cs {
+      // Deselecting aborts whatever command was in flight
+      self.state = State::Idle;
+      self.cs = false;
+      self.clk = clk;
+      return false;
+    }
+
+    let rising_edge = self.cs && !self.clk && clk;
+    self.cs = true;
+    self.clk = clk;
+
+    if rising_edge {
+      self.clock_bit();
+    }
+
+    std::mem::take(&mut self.written)
+  }
+
+  fn read_word(&self, address: usize) -> u16 {
+    u16::from_le_bytes([self.data[address * 2], self.data[address * 2 + 1]])
+  }
+
+  fn write_word(&mut self, address: usize, value: u16) {
+    let bytes = value.to_le_bytes();
+    self.data[address * 2] = bytes[0];
+    self.data[address * 2 + 1] = bytes[1];
+    self.written = true;
+  }
+
+  fn clock_bit(&mut self) {
+    let bit = self.di as u16;
+
+    self.state = match self.state {
+      State::Idle => {
+        if bit == 1 {
+          State::Command { bits: 0, count: 0 }
+        } else {
+          State::Idle
+        }
+      },
+      State::Command { bits, count } => {
+        let bits = (bits << 1) | bit;
+
+        if count + 1 < COMMAND_LENGTH {
+          State::Command { bits, count: count + 1 }
+        } else {
+          self.run_command(bits)
+        }
+      },
+      State::Read { address, data, count } => {
+        self.do_ = data & 0x8000 != 0;
+
+        if count + 1 < 16 {
+          State::Read { address, data: data << 1, count: count + 1 }
+        } else {
+          // Keeping CS high continues with the next word
+          let next = (address + 1) % WORD_COUNT;
+          State::Read { address: next, data: self.read_word(next), count: 0 }
+        }
+      },
+      State::Write { address, data, count } => {
+        let data = (data << 1) | bit;
+
+        if count + 1 < 16 {
+          State::Write { address, data, count: count + 1 }
+        } else {
+          if self.write_enabled {
+            match address {
+              Some(address) => self.write_word(address, data),
+              None => {
+                for address in 0..WORD_COUNT {
+                  self.write_word(address, data);
+                }
+              },
+            }
+          }
+
+          self.do_ = true;
+          State::Idle
+        }
+      },
+    };
+  }
+
+  fn run_command(&mut self, bits: u16) -> State {
+    let opcode = (bits >> 8) & 0b11;
+    let address = (bits & 0x7F) as usize;
+
+    match opcode {
+      0b10 => {
+        // READ, preceded by a dummy zero bit
+        self.do_ = false;
+        State::Read { address, data: self.read_word(address), count: 0 }
+      },
+      0b01 => State::Write { address: Some(address), data: 0, count: 0 },
+      0b11 => {
+        if self.write_enabled {
+          self.write_word(address, 0xFFFF);
+        }
+
+        self.do_ = true;
+        State::Idle
+      },
+      _ => {
+        match (bits >> 6) & 0b11 {
+          0b11 => self.write_enabled = true,
+          0b00 => self.write_enabled = false,
+          0b10 => {
+            if self.write_enabled {
+              self.data = [0xFF; EEPROM_SIZE];
+              self.written = true;
+            }
+
+            self.do_ = true;
+          },
+          _ => return State::Write { address: None, data: 0, count: 0 },
+        }
+
+        State::Idle
+      },
+    }
+  }
+}
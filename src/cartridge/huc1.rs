@@ -0,0 +1,89 @@
+use cartridge::mbc::read_rom_bank;
+use cartridge::mbc::Mbc;
+use cartridge::mbc::RAM_BANK_SIZE;
+use infrared::infrared::Infrared;
+
+pub struct Huc1 {
+  rom_bank: usize,
+  ram_bank: usize,
+  ram: Vec<u8>,
+  infrared_mode: bool,
+  pub infrared: Infrared,
+}
+
+impl Huc1 {
+  pub fn new(ram_size: usize) -> Self {
+    Self {
+      rom_bank: 1,
+      ram_bank: 0,
+      ram: vec![0; ram_size],
+      infrared_mode: false,
+      infrared: Infrared::new(),
+    }
+  }
+
+  fn ram_offset(&self, address: u16) -> Option<usize> {
+    if self.ram.is_empty() {
+      return None;
+    }
+
+    Some((self.ram_bank * RAM_BANK_SIZE + (address as usize - 0xA000)) % self.ram.len())
+  }
+}
+
+impl Mbc for Huc1 {
+  fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+    match address {
+      0x0000..=0x3FFF => read_rom_bank(rom, 0, address),
+      _ => read_rom_bank(rom, self.rom_bank, address),
+    }
+  }
+
+  fn write_rom(&mut self, address: u16, value: u8) {
+    match address {
+      0x0000..=0x1FFF => self.infrared_mode = value & 0x0F == 0x0E,
+      0x2000..=0x3FFF => self.rom_bank = ((value & 0x3F) as usize).max(1),
+      0x4000..=0x5FFF => self.ram_bank = (value & 0x03) as usize,
+      _ => {},
+    }
+  }
+
+  fn read_ram(&self, address: u16) -> u8 {
+    if self.infrared_mode {
+      // 0xC1 while light is seen, 0xC0 otherwise
+      return 0xC0 | self.infrared.receiving() as u8;
+    }
+
+    match self.ram_offset(address) {
+      Some(offset) => self.ram[offset],
+      None => 0xFF,
+    }
+  }
+
+  fn write_ram(&mut self, address: u16, value: u8) -> bool {
+    if self.infrared_mode {
+      self.infrared.set_led(value & 0x01 != 0);
+      return false;
+    }
+
+    match self.ram_offset(address) {
+      Some(offset) => {
+        self.ram[offset] = value;
+        true
+      },
+      None => false,
+    }
+  }
+
+  fn ram(&self) -> &[u8] {
+    &self.ram
+  }
+
+  fn ram_mut(&mut self) -> &mut [u8] {
+    &mut self.ram
+  }
+
+  fn infrared_mut(&mut self) -> Option<&mut Infrared> {
+    Some(&mut self.infrared)
+  }
+}
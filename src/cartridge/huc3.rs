@@ -0,0 +1,223 @@
+use cartridge::mbc::read_rom_bank;
+use cartridge::mbc::Mbc;
+use cartridge::mbc::RAM_BANK_SIZE;
+use cartridge::rtc::Rtc;
+use infrared::infrared::Infrared;
+
+// What 0xA000-0xBFFF maps to, selected by writes to 0x0000-0x1FFF
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Mode {
+  RamReadOnly,
+  Ram,
+  RtcCommand,
+  RtcResponse,
+  RtcSemaphore,
+  Infrared,
+  None,
+}
+
+pub struct Huc3 {
+  rom_bank: usize,
+  ram_bank: usize,
+  ram: Vec<u8>,
+  mode: Mode,
+  pub infrared: Infrared,
+
+  // The RTC counts minutes of the day and days, and is driven through
+  // nibble-wide commands addressing its own 256-nibble memory
+  rtc: Rtc,
+  rtc_memory: [u8; 256],
+  rtc_address: u8,
+  response: u8,
+  // Tone latched by the last speaker command, until the host takes it
+  speaker_tone: Option<u8>,
+}
+
+impl Huc3 {
+  pub fn new(ram_size: usize) -> Self {
+    Self {
+      rom_bank: 1,
+      ram_bank: 0,
+      ram: vec![0; ram_size],
+      mode: Mode::None,
+      infrared: Infrared::new(),
+      rtc: Rtc::new(0x1000),
+      rtc_memory: [0; 256],
+      rtc_address: 0,
+      response: 0,
+      speaker_tone: None,
+    }
+  }
+
+  fn minutes_of_day(&self) -> u16 {
+    self.rtc.hours as u16 * 60 + self.rtc.minutes as u16
+  }
+
+  fn ram_offset(&self, address: u16) -> Option<usize> {
+    if self.ram.is_empty() {
+      return None;
+    }
+
+    Some((self.ram_bank * RAM_BANK_SIZE + (address as usize - 0xA000)) % self.ram.len())
+  }
+
+  // Returns whether the command changed the clock or RTC memory
+  fn run_rtc_command(&mut self, value: u8) -> bool {
+    let command = value >> 4;
+    let argument = value & 0x0F;
+    let mut result = 0;
+    let mut changed = false;
+
+    match command {
+      // Read, then increment the address
+      0x1 => {
+        result = self.rtc_memory[self.rtc_address as usize];
+        self.rtc_address = self.rtc_address.wrapping_add(1);
+      },
+      // Write, then increment the address
+      0x3 => {
+        self.rtc_memory[self.rtc_address as usize] = argument;
+        self.rtc_address = self.rtc_address.wrapping_add(1);
+        changed = true;
+      },
+      0x4 => self.rtc_address = (self.rtc_address & 0xF0) | argument,
+      0x5 => self.rtc_address = (self.rtc_address & 0x0F) | (argument << 4),
+      0x6 => {
+        result = self.run_extended_command(argument);
+        changed = argument == 0x1;
+      },
+      _ => {},
+    }
+
+    self.response = (command << 4) | (result & 0x0F);
+    changed
+  }
+
+  fn run_extended_command(&mut self, argument: u8) -> u8 {
+    match argument {
+      // Copy the clock into RTC memory 0x00-0x05
+      0x0 => {
+        self.rtc.update();
+
+        let minutes = self.minutes_of_day();
+        for i in 0..3 {
+          self.rtc_memory[i] = ((minutes >> (i * 4)) & 0x0F) as u8;
+          self.rtc_memory[i + 3] = ((self.rtc.days >> (i * 4)) & 0x0F) as u8;
+        }
+
+        0
+      },
+      // Set the clock from RTC memory 0x00-0x05
+      0x1 => {
+        self.rtc.update();
+
+        let (mut minutes, mut days) = (0u16, 0u16);
+        for i in 0..3 {
+          minutes |= (self.rtc_memory[i] as u16) << (i * 4);
+          days |= (self.rtc_memory[i + 3] as u16) << (i * 4);
+        }
+
+        let minutes = minutes % (24 * 60);
+        self.rtc.hours = (minutes / 60) as u8;
+        self.rtc.minutes = (minutes % 60) as u8;
+        self.rtc.seconds = 0;
+        self.rtc.days = days;
+
+        0
+      },
+      // Status, always ready
+      0x2 => 1,
+      // Play the speaker tone stored at 0x27
+      0xE => {
+        self.speaker_tone = Some(self.rtc_memory[0x27]);
+        0
+      },
+      _ => 0,
+    }
+  }
+}
+
+impl Mbc for Huc3 {
+  fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+    match address {
+      0x0000..=0x3FFF => read_rom_bank(rom, 0, address),
+      _ => read_rom_bank(rom, self.rom_bank, address),
+    }
+  }
+
+  fn write_rom(&mut self, address: u16, value: u8) {
+    match address {
+      0x0000..=0x1FFF => {
+        self.mode = match value & 0x0F {
+          0x0 => Mode::RamReadOnly,
+          0xA => Mode::Ram,
+          0xB => Mode::RtcCommand,
+          0xC => Mode::RtcResponse,
+          0xD => Mode::RtcSemaphore,
+          0xE => Mode::Infrared,
+          _ => Mode::None,
+        }
+      },
+      0x2000..=0x3FFF => self.rom_bank = (value & 0x7F) as usize,
+      0x4000..=0x5FFF => self.ram_bank = (value & 0x03) as usize,
+      _ => {},
+    }
+  }
+
+  fn read_ram(&self, address: u16) -> u8 {
+    match self.mode {
+      Mode::RamReadOnly | Mode::Ram => {
+        match self.ram_offset(address) {
+          Some(offset) => self.ram[offset],
+          None => 0xFF,
+        }
+      },
+      Mode::RtcResponse => 0x80 | self.response,
+      // Commands complete immediately
+      Mode::RtcSemaphore => 0xFF,
+      Mode::Infrared => 0xC0 | self.infrared.receiving() as u8,
+      Mode::RtcCommand | Mode::None => 0xFF,
+    }
+  }
+
+  fn write_ram(&mut self, address: u16, value: u8) -> bool {
+    match self.mode {
+      Mode::Ram => {
+        if let Some(offset) = self.ram_offset(address) {
+          self.ram[offset] = value;
+          return true;
+        }
+      },
+      // Commands can set the clock, which is saved with the RAM
+      Mode::RtcCommand => return self.run_rtc_command(value),
+      Mode::Infrared => self.infrared.set_led(value & 0x01 != 0),
+      _ => {},
+    }
+
+    false
+  }
+
+  fn ram(&self) -> &[u8] {
+    &self.ram
+  }
+
+  fn ram_mut(&mut self) -> &mut [u8] {
+    &mut self.ram
+  }
+
+  fn rtc_state(&mut self) -> Option<Vec<u8>> {
+    Some(self.rtc.footer())
+  }
+
+  fn load_rtc_state(&mut self, data: &[u8]) {
+    self.rtc.load_footer(data);
+  }
+
+  fn infrared_mut(&mut self) -> Option<&mut Infrared> {
+    Some(&mut self.infrared)
+  }
+
+  fn take_speaker_tone(&mut self) -> Option<u8> {
+    self.speaker_tone.take()
+  }
+}
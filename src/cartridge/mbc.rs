@@ -0,0 +1,137 @@
+use cartridge::camera_sensor::CameraSensor;
+use cartridge::eeprom::Eeprom;
+use infrared::infrared::Infrared;
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+pub trait Mbc {
+  // 0x0000-0x7FFF
+  fn read_rom(&self, rom: &[u8], address: u16) -> u8;
+  fn write_rom(&mut self, address: u16, value: u8);
+
+  // 0xA000-0xBFFF
+  fn read_ram(&self, address: u16) -> u8;
+  // Returns whether the write changed what the battery keeps
+  fn write_ram(&mut self, address: u16, value: u8) -> bool;
+
+  // T-cycles elapsed since the last call, for mappers with their own
+  // hardware. Returns whether that hardware wrote to RAM.
+  fn tick(&mut self, _cycles: u64) -> bool {
+    false
+  }
+
+  // External RAM, whole and unbanked
+  fn ram(&self) -> &[u8] {
+    &[]
+  }
+
+  fn ram_mut(&mut self) -> &mut [u8] {
+    &mut []
+  }
+
+  // Clock state kept by the battery next to the RAM
+  fn rtc_state(&mut self) -> Option<Vec<u8>> {
+    None
+  }
+
+  fn load_rtc_state(&mut self, _data: &[u8]) {}
+
+  // Host-provided tilt in g, for cartridges with an accelerometer
+  fn set_tilt(&mut self, _x: f32, _y: f32) {}
+
+  fn eeprom(&self) -> Option<&Eeprom> {
+    None
+  }
+
+  fn eeprom_mut(&mut self) -> Option<&mut Eeprom> {
+    None
+  }
+
+  fn infrared_mut(&mut self) -> Option<&mut Infrared> {
+    None
+  }
+
+  fn set_camera_sensor(&mut self, _sensor: Box<dyn CameraSensor>) {}
+
+  // Tone the cartridge's own speaker was last asked to play
+  fn take_speaker_tone(&mut self) -> Option<u8> {
+    None
+  }
+}
+
+pub fn read_rom_bank(rom: &[u8], bank: usize, address: u16) -> u8 {
+  let offset = bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1));
+
+  if rom.is_empty() {
+    return 0xFF;
+  }
+
+  rom[offset % rom.len()]
+}
+
+// Cartridges without a mapper, and any mapper we do not support yet
+pub struct RomOnly;
+
+impl Mbc for RomOnly {
+  fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+    read_rom_bank(rom, (address as usize) / ROM_BANK_SIZE, address)
+  }
+
+  fn write_rom(&mut self, _address: u16, _value: u8) {}
+
+  fn read_ram(&self, _address: u16) -> u8 {
+    0xFF
+  }
+
+  fn write_ram(&mut self, _address: u16, _value: u8) -> bool {
+    false
+  }
+}
+
+// 32 KiB of ROM with up to 8 KiB of RAM wired straight to 0xA000-0xBFFF
+pub struct RomRam {
+  ram: Vec<u8>,
+}
+
+impl RomRam {
+  pub fn new(ram_size: usize) -> Self {
+    Self {
+      ram: vec![0; ram_size.min(RAM_BANK_SIZE)],
+    }
+  }
+}
+
+impl Mbc for RomRam {
+  fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+    read_rom_bank(rom, (address as usize) / ROM_BANK_SIZE, address)
+  }
+
+  fn write_rom(&mut self, _address: u16, _value: u8) {}
+
+  fn read_ram(&self, address: u16) -> u8 {
+    if self.ram.is_empty() {
+      return 0xFF;
+    }
+
+    self.ram[(address as usize - 0xA000) % self.ram.len()]
+  }
+
+  fn write_ram(&mut self, address: u16, value: u8) -> bool {
+    if self.ram.is_empty() {
+      return false;
+    }
+
+    let offset = (address as usize - 0xA000) % self.ram.len();
+    self.ram[offset] = value;
+    true
+  }
+
+  fn ram(&self) -> &[u8] {
+    &self.ram
+  }
+
+  fn ram_mut(&mut self) -> &mut [u8] {
+    &mut self.ram
+  }
+}
@@ -0,0 +1,79 @@
+use cartridge::mbc::read_rom_bank;
+use cartridge::mbc::Mbc;
+use cartridge::mbc::RAM_BANK_SIZE;
+
+pub struct Mbc1 {
+  // 5 low bits of the ROM bank, never 0
+  bank1: usize,
+  // 2 bits that extend the ROM bank, or select the RAM bank in mode 1
+  bank2: usize,
+  advanced_banking: bool,
+  ram_enabled: bool,
+  ram: Vec<u8>,
+}
+
+impl Mbc1 {
+  pub fn new(ram_size: usize) -> Self {
+    Self {
+      bank1: 1,
+      bank2: 0,
+      advanced_banking: false,
+      ram_enabled: false,
+      ram: vec![0; ram_size],
+    }
+  }
+
+  fn ram_offset(&self, address: u16) -> Option<usize> {
+    if self.ram.is_empty() {
+      return None;
+    }
+
+    let bank = if self.advanced_banking { self.bank2 } else { 0 };
+    Some((bank * RAM_BANK_SIZE + (address as usize - 0xA000)) % self.ram.len())
+  }
+}
+
+impl Mbc for Mbc1 {
+  fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+    match address {
+      // Mode 1 also maps bank2 into the lower half, for 1 MiB and larger ROMs
+      0x0000..=0x3FFF if self.advanced_banking => read_rom_bank(rom, self.bank2 << 5, address),
+      0x0000..=0x3FFF => read_rom_bank(rom, 0, address),
+      _ => read_rom_bank(rom, (self.bank2 << 5) | self.bank1, address),
+    }
+  }
+
+  fn write_rom(&mut self, address: u16, value: u8) {
+    match address {
+      0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+      0x2000..=0x3FFF => self.bank1 = ((value & 0x1F) as usize).max(1),
+      0x4000..=0x5FFF => self.bank2 = (value & 0x03) as usize,
+      _ => self.advanced_banking = value & 0x01 != 0,
+    }
+  }
+
+  fn read_ram(&self, address: u16) -> u8 {
+    match self.ram_offset(address) {
+      Some(offset) if self.ram_enabled => self.ram[offset],
+      _ => 0xFF,
+    }
+  }
+
+  fn write_ram(&mut self, address: u16, value: u8) -> bool {
+    match self.ram_offset(address) {
+      Some(offset) if self.ram_enabled => {
+        self.ram[offset] = value;
+        true
+      },
+      _ => false,
+    }
+  }
+
+  fn ram(&self) -> &[u8] {
+    &self.ram
+  }
+
+  fn ram_mut(&mut self) -> &mut [u8] {
+    &mut self.ram
+  }
+}
@@ -0,0 +1,65 @@
+use cartridge::mbc::read_rom_bank;
+use cartridge::mbc::Mbc;
+
+// MBC2 has 512 half-bytes of RAM built in, whatever the header says
+const RAM_SIZE: usize = 0x200;
+
+pub struct Mbc2 {
+  rom_bank: usize,
+  ram_enabled: bool,
+  ram: Vec<u8>,
+}
+
+impl Mbc2 {
+  pub fn new() -> Self {
+    Self {
+      rom_bank: 1,
+      ram_enabled: false,
+      ram: vec![0; RAM_SIZE],
+    }
+  }
+}
+
+impl Mbc for Mbc2 {
+  fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+    match address {
+      0x0000..=0x3FFF => read_rom_bank(rom, 0, address),
+      _ => read_rom_bank(rom, self.rom_bank, address),
+    }
+  }
+
+  // Address bit 8 picks between RAM enable and the ROM bank
+  fn write_rom(&mut self, address: u16, value: u8) {
+    match address {
+      0x0000..=0x3FFF if address & 0x0100 == 0 => self.ram_enabled = value & 0x0F == 0x0A,
+      0x0000..=0x3FFF => self.rom_bank = ((value & 0x0F) as usize).max(1),
+      _ => {},
+    }
+  }
+
+  // The RAM repeats through 0xA000-0xBFFF and its upper nibble reads 1
+  fn read_ram(&self, address: u16) -> u8 {
+    if !self.ram_enabled {
+      return 0xFF;
+    }
+
+    0xF0 | self.ram[address as usize % RAM_SIZE]
+  }
+
+  fn write_ram(&mut self, address: u16, value: u8) -> bool {
+    if !self.ram_enabled {
+      return false;
+    }
+
+    self.ram[address as usize % RAM_SIZE] = value & 0x0F;
+    true
+  }
+
+  fn ram(&self) -> &[u8] {
+    &self.ram
+  }
+
+  fn ram_mut(&mut self) -> &mut [u8] {
+    &mut self.ram
+  }
+}
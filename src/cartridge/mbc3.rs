@@ -0,0 +1,113 @@
+use cartridge::mbc::read_rom_bank;
+use cartridge::mbc::Mbc;
+use cartridge::mbc::RAM_BANK_SIZE;
+use cartridge::rtc::Rtc;
+
+pub struct Mbc3 {
+  rom_bank: usize,
+  ram_rtc_select: u8,
+  ram_enabled: bool,
+  ram: Vec<u8>,
+  rtc: Option<Rtc>,
+  latch_armed: bool,
+}
+
+impl Mbc3 {
+  pub fn new(ram_size: usize, has_rtc: bool) -> Self {
+    Self {
+      rom_bank: 1,
+      ram_rtc_select: 0,
+      ram_enabled: false,
+      ram: vec![0; ram_size],
+      rtc: if has_rtc { Some(Rtc::new(0x200)) } else { None },
+      latch_armed: false,
+    }
+  }
+
+  fn ram_offset(&self, address: u16) -> Option<usize> {
+    if self.ram.is_empty() || self.ram_rtc_select > 0x03 {
+      return None;
+    }
+
+    Some((self.ram_rtc_select as usize * RAM_BANK_SIZE + (address as usize - 0xA000)) % self.ram.len())
+  }
+}
+
+impl Mbc for Mbc3 {
+  fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+    match address {
+      0x0000..=0x3FFF => read_rom_bank(rom, 0, address),
+      _ => read_rom_bank(rom, self.rom_bank, address),
+    }
+  }
+
+  fn write_rom(&mut self, address: u16, value: u8) {
+    match address {
+      0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+      0x2000..=0x3FFF => self.rom_bank = ((value & 0x7F) as usize).max(1),
+      0x4000..=0x5FFF => self.ram_rtc_select = value & 0x0F,
+      _ => {
+        // Writing 0x00 then 0x01 latches the clock
+        if value == 0x01 && self.latch_armed {
+          if let Some(rtc) = &mut self.rtc {
+            rtc.latch();
+          }
+        }
+
+        self.latch_armed = value == 0x00;
+      },
+    }
+  }
+
+  fn read_ram(&self, address: u16) -> u8 {
+    if !self.ram_enabled {
+      return 0xFF;
+    }
+
+    if let (0x08..=0x0C, Some(rtc)) = (self.ram_rtc_select, &self.rtc) {
+      return rtc.read_register(self.ram_rtc_select);
+    }
+
+    match self.ram_offset(address) {
+      Some(offset) => self.ram[offset],
+      None => 0xFF,
+    }
+  }
+
+  fn write_ram(&mut self, address: u16, value: u8) -> bool {
+    if !self.ram_enabled {
+      return false;
+    }
+
+    if let (0x08..=0x0C, Some(rtc)) = (self.ram_rtc_select, &mut self.rtc) {
+      rtc.write_register(self.ram_rtc_select, value);
+      return true;
+    }
+
+    match self.ram_offset(address) {
+      Some(offset) => {
+        self.ram[offset] = value;
+        true
+      },
+      None => false,
+    }
+  }
+
+  fn ram(&self) -> &[u8] {
+    &self.ram
+  }
+
+  fn ram_mut(&mut self) -> &mut [u8] {
+    &mut self.ram
+  }
+
+  fn rtc_state(&mut self) -> Option<Vec<u8>> {
+    self.rtc.as_mut().map(|rtc| rtc.footer())
+  }
+
+  fn load_rtc_state(&mut self, data: &[u8]) {
+    if let Some(rtc) = &mut self.rtc {
+      rtc.load_footer(data);
+    }
+  }
+}
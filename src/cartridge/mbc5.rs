@@ -0,0 +1,78 @@
+use cartridge::mbc::read_rom_bank;
+use cartridge::mbc::Mbc;
+use cartridge::mbc::RAM_BANK_SIZE;
+
+pub struct Mbc5 {
+  // 9 bits, and unlike older mappers bank 0 can be mapped at 0x4000
+  rom_bank: usize,
+  ram_bank: usize,
+  ram_enabled: bool,
+  ram: Vec<u8>,
+  // Rumble carts drive the motor with bit 3 of the RAM bank
+  rumble: bool,
+}
+
+impl Mbc5 {
+  pub fn new(ram_size: usize, rumble: bool) -> Self {
+    Self {
+      rom_bank: 1,
+      ram_bank: 0,
+      ram_enabled: false,
+      ram: vec![0; ram_size],
+      rumble,
+    }
+  }
+
+  fn ram_offset(&self, address: u16) -> Option<usize> {
+    if self.ram.is_empty() {
+      return None;
+    }
+
+    Some((self.ram_bank * RAM_BANK_SIZE + (address as usize - 0xA000)) % self.ram.len())
+  }
+}
+
+impl Mbc for Mbc5 {
+  fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+    match address {
+      0x0000..=0x3FFF => read_rom_bank(rom, 0, address),
+      _ => read_rom_bank(rom, self.rom_bank, address),
+    }
+  }
+
+  fn write_rom(&mut self, address: u16, value: u8) {
+    match address {
+      0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+      0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as usize,
+      0x3000..=0x3FFF => self.rom_bank = (self.rom_bank & 0xFF) | ((value as usize & 0x01) << 8),
+      0x4000..=0x5FFF if self.rumble => self.ram_bank = (value & 0x07) as usize,
+      0x4000..=0x5FFF => self.ram_bank = (value & 0x0F) as usize,
+      _ => {},
+    }
+  }
+
+  fn read_ram(&self, address: u16) -> u8 {
+    match self.ram_offset(address) {
+      Some(offset) if self.ram_enabled => self.ram[offset],
+      _ => 0xFF,
+    }
+  }
+
+  fn write_ram(&mut self, address: u16, value: u8) -> bool {
+    match self.ram_offset(address) {
+      Some(offset) if self.ram_enabled => {
+        self.ram[offset] = value;
+        true
+      },
+      _ => false,
+    }
+  }
+
+  fn ram(&self) -> &[u8] {
+    &self.ram
+  }
+
+  fn ram_mut(&mut self) -> &mut [u8] {
+    &mut self.ram
+  }
+}
@@ -0,0 +1,115 @@
+use cartridge::eeprom::Eeprom;
+use cartridge::mbc::read_rom_bank;
+use cartridge::mbc::Mbc;
+
+// Accelerometer output at rest and per g of tilt
+const ACCELEROMETER_CENTER: f32 = 0x81D0 as f32;
+const ACCELEROMETER_GRAVITY: f32 = 0x70 as f32;
+const ACCELEROMETER_ERASED: u16 = 0x8000;
+
+pub struct Mbc7 {
+  rom_bank: usize,
+  ram_enable_1: bool,
+  ram_enable_2: bool,
+  tilt_x: f32,
+  tilt_y: f32,
+  latch_ready: bool,
+  latched_x: u16,
+  latched_y: u16,
+  pub eeprom: Eeprom,
+}
+
+impl Mbc7 {
+  pub fn new() -> Self {
+    Self {
+      rom_bank: 1,
+      ram_enable_1: false,
+      ram_enable_2: false,
+      tilt_x: 0.0,
+      tilt_y: 0.0,
+      latch_ready: false,
+      latched_x: ACCELEROMETER_ERASED,
+      latched_y: ACCELEROMETER_ERASED,
+      eeprom: Eeprom::new(),
+    }
+  }
+
+  fn registers_enabled(&self) -> bool {
+    self.ram_enable_1 && self.ram_enable_2
+  }
+
+  fn accelerometer_value(tilt: f32) -> u16 {
+    (ACCELEROMETER_CENTER + tilt * ACCELEROMETER_GRAVITY).clamp(0.0, u16::MAX as f32) as u16
+  }
+}
+
+impl Mbc for Mbc7 {
+  fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+    match address {
+      0x0000..=0x3FFF => read_rom_bank(rom, 0, address),
+      _ => read_rom_bank(rom, self.rom_bank, address),
+    }
+  }
+
+  fn write_rom(&mut self, address: u16, value: u8) {
+    match address {
+      0x0000..=0x1FFF => self.ram_enable_1 = value == 0x0A,
+      0x2000..=0x3FFF => self.rom_bank = (value & 0x7F) as usize,
+      0x4000..=0x5FFF => self.ram_enable_2 = value == 0x40,
+      _ => {},
+    }
+  }
+
+  fn read_ram(&self, address: u16) -> u8 {
+    if !self.registers_enabled() || address >= 0xB000 {
+      return 0xFF;
+    }
+
+    // Registers are selected by address bits 4-7 and mirrored across 0xA000-0xAFFF
+    match (address >> 4) & 0x0F {
+      0x2 => self.latched_x as u8,
+      0x3 => (self.latched_x >> 8) as u8,
+      0x4 => self.latched_y as u8,
+      0x5 => (self.latched_y >> 8) as u8,
+      0x6 => 0x00,
+      0x8 => self.eeprom.read_pins(),
+      _ => 0xFF,
+    }
+  }
+
+  fn write_ram(&mut self, address: u16, value: u8) -> bool {
+    if !self.registers_enabled() || address >= 0xB000 {
+      return false;
+    }
+
+    match (address >> 4) & 0x0F {
+      0x0 if value == 0x55 => {
+        self.latched_x = ACCELEROMETER_ERASED;
+        self.latched_y = ACCELEROMETER_ERASED;
+        self.latch_ready = true;
+      },
+      0x1 if value == 0xAA && self.latch_ready => {
+        self.latched_x = Self::accelerometer_value(self.tilt_x);
+        self.latched_y = Self::accelerometer_value(self.tilt_y);
+        self.latch_ready = false;
+      },
+      0x8 => return self.eeprom.write_pins(value),
+      _ => {},
+    }
+
+    false
+  }
+
+  fn set_tilt(&mut self, x: f32, y: f32) {
+    self.tilt_x = x;
+    self.tilt_y = y;
+  }
+
+  fn eeprom(&self) -> Option<&Eeprom> {
+    Some(&self.eeprom)
+  }
+
+  fn eeprom_mut(&mut self) -> Option<&mut Eeprom> {
+    Some(&mut self.eeprom)
+  }
+}
@@ -0,0 +1,14 @@
+pub mod camera;
+pub mod camera_sensor;
+pub mod cartridge;
+pub mod eeprom;
+pub mod huc1;
+pub mod huc3;
+pub mod mbc;
+pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
+pub mod mbc7;
+pub mod rtc;
+pub mod save;
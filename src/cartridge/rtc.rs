@@ -0,0 +1,176 @@
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+// Real-time clock shared by MBC3 and HuC3, saved in the 48-byte footer
+// BGB and VBA-M append to `.sav` files: the live and latched seconds,
+// minutes, hours, days low and days high as 32-bit words, followed by the
+// UNIX time of the save as a 64-bit word. Older files use a 32-bit time.
+pub const FOOTER_LENGTH: usize = 48;
+const LEGACY_FOOTER_LENGTH: usize = 44;
+
+pub fn unix_time() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_secs())
+    .unwrap_or(0)
+}
+
+pub struct Rtc {
+  pub seconds: u8,
+  pub minutes: u8,
+  pub hours: u8,
+  pub days: u16,
+  pub halted: bool,
+  pub day_carry: bool,
+  latched: [u8; 5],
+  day_count: u32,
+  last_update: u64,
+}
+
+impl Rtc {
+  // Days wrap at day_count: 512 on MBC3, 4096 on HuC3
+  pub fn new(day_count: u32) -> Self {
+    Self {
+      seconds: 0,
+      minutes: 0,
+      hours: 0,
+      days: 0,
+      halted: false,
+      day_carry: false,
+      latched: [0; 5],
+      day_count,
+      last_update: unix_time(),
+    }
+  }
+
+  pub fn advance(&mut self, seconds: u64) {
+    if self.halted {
+      return;
+    }
+
+    let total = self.seconds as u64 + seconds;
+    let minutes = self.minutes as u64 + total / 60;
+    let hours = self.hours as u64 + minutes / 60;
+    let days = self.days as u64 + hours / 24;
+
+    self.seconds = (total % 60) as u8;
+    self.minutes = (minutes % 60) as u8;
+    self.hours = (hours % 24) as u8;
+
+    if days >= self.day_count as u64 {
+      self.day_carry = true;
+    }
+    self.days = (days % self.day_count as u64) as u16;
+  }
+
+  // Catches up with the host clock
+  pub fn update(&mut self) {
+    let now = unix_time();
+
+    if now > self.last_update {
+      self.advance(now - self.last_update);
+    }
+
+    self.last_update = now;
+  }
+
+  // Bit 0: day bit 8, bit 6: halt, bit 7: day carry
+  pub fn days_high(&self) -> u8 {
+    ((self.days >> 8) as u8 & 0x01) | ((self.halted as u8) << 6) | ((self.day_carry as u8) << 7)
+  }
+
+  pub fn latch(&mut self) {
+    self.update();
+    self.latched = [self.seconds, self.minutes, self.hours, self.days as u8, self.days_high()];
+  }
+
+  // MBC3 registers 0x08-0x0C read the latched copy
+  pub fn read_register(&self, register: u8) -> u8 {
+    match register {
+      0x08..=0x0C => self.latched[(register - 0x08) as usize],
+      _ => 0xFF,
+    }
+  }
+
+  pub fn write_register(&mut self, register: u8, value: u8) {
+    self.update();
+
+    match register {
+      0x08 => self.seconds = value % 60,
+      0x09 => self.minutes = value % 60,
+      0x0A => self.hours = value % 24,
+      0x0B => self.days = (self.days & 0x100) | value as u16,
+      0x0C => {
+        self.days = (self.days & 0xFF) | ((value as u16 & 0x01) << 8);
+        self.halted = value & 0x40 != 0;
+        self.day_carry = value & 0x80 != 0;
+      },
+      _ => {},
+    }
+  }
+
+  pub fn footer(&mut self) -> Vec<u8> {
+    self.update();
+
+    // HuC3 counts up to 4095 days, keep the bits above 8 in days high
+    let days_high = if self.day_count > 0x200 {
+      (self.days >> 8) as u8
+    } else {
+      self.days_high()
+    };
+
+    let live = [self.seconds, self.minutes, self.hours, self.days as u8, days_high];
+
+    let mut footer = Vec::with_capacity(FOOTER_LENGTH);
+    for value in live.iter().chain(self.latched.iter()) {
+      footer.extend_from_slice(&(*value as u32).to_le_bytes());
+    }
+    footer.extend_from_slice(&self.last_update.to_le_bytes());
+
+    footer
+  }
+
+  pub fn load_footer(&mut self, footer: &[u8]) -> bool {
+    if footer.len() != FOOTER_LENGTH && footer.len() != LEGACY_FOOTER_LENGTH {
+      return false;
+    }
+
+    let word = |index: usize| -> u32 {
+      let offset = index * 4;
+      u32::from_le_bytes([footer[offset], footer[offset + 1], footer[offset + 2], footer[offset + 3]])
+    };
+
+    let days_high = word(4);
+
+    self.seconds = (word(0) % 60) as u8;
+    self.minutes = (word(1) % 60) as u8;
+    self.hours = (word(2) % 24) as u8;
+
+    if self.day_count > 0x200 {
+      self.days = ((((days_high & 0x0F) << 8) | (word(3) & 0xFF)) % self.day_count) as u16;
+      self.halted = false;
+      self.day_carry = false;
+    } else {
+      self.days = (((days_high & 0x01) << 8) | (word(3) & 0xFF)) as u16;
+      self.halted = days_high & 0x40 != 0;
+      self.day_carry = days_high & 0x80 != 0;
+    }
+
+    for i in 0..5 {
+      self.latched[i] = word(5 + i) as u8;
+    }
+
+    self.last_update = if footer.len() == FOOTER_LENGTH {
+      let mut timestamp = [0; 8];
+      timestamp.copy_from_slice(&footer[40..48]);
+      u64::from_le_bytes(timestamp)
+    } else {
+      word(10) as u64
+    };
+
+    // Fast-forward by the time spent switched off
+    self.update();
+
+    true
+  }
+}
@@ -0,0 +1,41 @@
+use std::fs;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::io::Result;
+use std::io::Write;
+use std::path::Path;
+
+// Battery-backed data lives next to the ROM. `.sav` files are the raw
+// external RAM followed by the mapper's RTC footer, if any, which is the
+// layout other emulators read and write.
+
+// Battery-backed types among the mappers we support. MMM01 (0x0D) is left
+// out as it falls back to a mapper without RAM.
+pub fn has_battery(cartridge_type: u8) -> bool {
+  matches!(
+    cartridge_type,
+    0x03 | 0x06 | 0x09 | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFC | 0xFE | 0xFF
+  )
+}
+
+pub fn read_if_exists(path: &Path) -> Result<Option<Vec<u8>>> {
+  match fs::read(path) {
+    Ok(data) => Ok(Some(data)),
+    Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(None),
+    Err(e) => Err(e),
+  }
+}
+
+// Writes to a temporary file first so that a crash mid-write never leaves
+// a truncated save behind
+pub fn write_atomically(path: &Path, data: &[u8]) -> Result<()> {
+  let mut temporary_path = path.as_os_str().to_owned();
+  temporary_path.push(".tmp");
+  let temporary_path = Path::new(&temporary_path);
+
+  let mut file = File::create(temporary_path)?;
+  file.write_all(data)?;
+  file.sync_all()?;
+
+  fs::rename(temporary_path, path)
+}
@@ -20,6 +20,8 @@ pub struct Cpu {
   pub prefixed_optable: PrefixedOptable,
   pub cycles: u64,
   pub cycles_table: CycleTable,
+  // Set by STOP, the clock stands still until a button is pressed
+  pub stopped: bool,
 }
 
 impl Cpu {
@@ -29,11 +31,20 @@ impl Cpu {
       optable: Optable::new(),
       prefixed_optable: PrefixedOptable::new(),
       cycles_table: CycleTable::new(),
-      cycles: 0
+      cycles: 0,
+      stopped: false,
     }
   }
 
   pub fn run_instruction(&mut self, memory: &mut Memory) {
+    if self.stopped {
+      if !memory.joypad.any_line_low() {
+        return;
+      }
+
+      self.stopped = false;
+    }
+
     let opcode = memory.read(self.registers.pc);
 
     if opcode == 0xCB {
@@ -115,7 +126,17 @@ impl Cpu {
       }
     }
 
-    self.cycles += self.cycles_table.cycle_table[opcode as usize];
+    let cycles = self.cycles_table.cycle_table[opcode as usize];
+    self.cycles += cycles;
+    memory.tick(cycles);
+
+    // DMA that halts the CPU, such as CGB VRAM DMA, runs the clock on
+    let mut stall = memory.take_stall_cycles();
+    while stall > 0 {
+      self.cycles += stall;
+      memory.tick(stall);
+      stall = memory.take_stall_cycles();
+    }
   }
 
   pub fn handle_flags(&mut self, z: Option<bool>, n: Option<bool>, h: Option<bool>, c: Option<bool>) {
@@ -1038,8 +1059,13 @@ impl Cpu {
     self.registers.pc += 2;
   }
 
-  fn stop(&mut self, _memory: &mut Memory) {
-    // TODO
+  fn stop(&mut self, memory: &mut Memory) {
+    // On CGB an armed KEY1 turns STOP into a speed switch, otherwise the
+    // CPU and LCD sleep until a button is pressed
+    if !memory.stop() {
+      self.stopped = true;
+    }
+
     self.registers.pc += 2;
   }
 
@@ -0,0 +1,145 @@
+use std::fs;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+use std::path::Path;
+
+use cartridge::mbc::read_rom_bank;
+use cartridge::mbc::Mbc;
+use cartridge::mbc::RAM_BANK_SIZE;
+use cartridge::mbc::ROM_BANK_SIZE;
+
+const HEADER_LENGTH: usize = 0x70;
+const TEXT_LENGTH: usize = 32;
+
+// Game Boy Sound System music files: a 0x70-byte header followed by the
+// game's sound driver and music data, loaded at a fixed address in ROM
+pub struct Gbs {
+  pub song_count: u8,
+  pub first_song: u8, // 1-based
+  pub load_address: u16,
+  pub init_address: u16,
+  pub play_address: u16,
+  pub stack_pointer: u16,
+  pub timer_modulo: u8,
+  // TAC, with bit 2 picking timer-driven play and bit 7 CGB double speed
+  pub timer_control: u8,
+  pub title: String,
+  pub author: String,
+  pub copyright: String,
+  pub data: Vec<u8>,
+}
+
+impl Gbs {
+  pub fn from_file(path: &Path) -> Result<Self> {
+    Self::parse(&fs::read(path)?)
+  }
+
+  pub fn parse(file: &[u8]) -> Result<Self> {
+    if file.len() < HEADER_LENGTH || &file[0..3] != b"GBS" {
+      return Err(Error::new(ErrorKind::InvalidData, "Not a GBS file"));
+    }
+
+    if file[0x03] != 1 {
+      return Err(Error::new(ErrorKind::InvalidData, "Only version 1 GBS files are supported"));
+    }
+
+    let word = |offset: usize| u16::from_le_bytes([file[offset], file[offset + 1]]);
+    let text = |offset: usize| {
+      file[offset..offset + TEXT_LENGTH].iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as char)
+        .collect()
+    };
+
+    let gbs = Self {
+      song_count: file[0x04],
+      first_song: file[0x05].max(1),
+      load_address: word(0x06),
+      init_address: word(0x08),
+      play_address: word(0x0A),
+      stack_pointer: word(0x0C),
+      timer_modulo: file[0x0E],
+      timer_control: file[0x0F],
+      title: text(0x10),
+      author: text(0x30),
+      copyright: text(0x50),
+      data: file[HEADER_LENGTH..].to_vec(),
+    };
+
+    if gbs.load_address >= 0x8000 {
+      return Err(Error::new(ErrorKind::InvalidData, "GBS load address is outside ROM"));
+    }
+
+    Ok(gbs)
+  }
+
+  // Play is called on the timer interrupt rather than on VBlank
+  pub fn uses_timer(&self) -> bool {
+    self.timer_control & 0x04 != 0
+  }
+
+  pub fn double_speed(&self) -> bool {
+    self.timer_control & 0x80 != 0
+  }
+
+  // The data at its load address in a ROM of whole banks. The RST vectors
+  // jump to the same offsets from the load address, where the file's own
+  // handlers sit.
+  pub fn rom(&self) -> Vec<u8> {
+    let load_address = self.load_address as usize;
+    let length = load_address + self.data.len();
+
+    let mut rom = vec![0; length.div_ceil(ROM_BANK_SIZE).max(2) * ROM_BANK_SIZE];
+    rom[load_address..length].copy_from_slice(&self.data);
+
+    if load_address >= 0x40 {
+      for vector in (0x00..0x40).step_by(8) {
+        let target = (self.load_address + vector as u16).to_le_bytes();
+        rom[vector..vector + 3].copy_from_slice(&[0xC3, target[0], target[1]]);
+      }
+    }
+
+    rom
+  }
+}
+
+// ROM banking as on MBC1, writing the 0x4000-0x7FFF bank to 0x2000-0x3FFF,
+// and 8 KiB of RAM that is always enabled
+pub struct GbsMapper {
+  rom_bank: usize,
+  ram: Vec<u8>,
+}
+
+impl GbsMapper {
+  pub fn new() -> Self {
+    Self {
+      rom_bank: 1,
+      ram: vec![0; RAM_BANK_SIZE],
+    }
+  }
+}
+
+impl Mbc for GbsMapper {
+  fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+    match address {
+      0x0000..=0x3FFF => read_rom_bank(rom, 0, address),
+      _ => read_rom_bank(rom, self.rom_bank, address),
+    }
+  }
+
+  fn write_rom(&mut self, address: u16, value: u8) {
+    if let 0x2000..=0x3FFF = address {
+      self.rom_bank = (value as usize).max(1);
+    }
+  }
+
+  fn read_ram(&self, address: u16) -> u8 {
+    self.ram[address as usize - 0xA000]
+  }
+
+  fn write_ram(&mut self, address: u16, value: u8) -> bool {
+    self.ram[address as usize - 0xA000] = value;
+    true
+  }
+}
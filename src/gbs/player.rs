@@ -0,0 +1,166 @@
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+
+use cartridge::cartridge::Cartridge;
+use cpu::cpu::Cpu;
+use cpu::cpu::MASTER_CLOCK_SPEED;
+use gbs::gbs::Gbs;
+use gbs::gbs::GbsMapper;
+use memory::memory::Memory;
+use timer::timer::TIMER_INTERRUPT;
+
+const IF_ADDRESS: u16 = 0xFF0F;
+
+// Where the init and play routines return to. Nothing is mapped there, so
+// the driver never runs into it by itself.
+const RETURN_ADDRESS: u16 = 0xFEA0;
+
+// T-cycles per frame, how often VBlank-driven drivers get called
+const FRAME_CYCLES: u64 = 70224;
+
+// A routine still running after this many cycles is taken to hang, e.g.
+// waiting for an interrupt that never comes
+const CALL_TIMEOUT: u64 = MASTER_CLOCK_SPEED as u64;
+
+// Runs a GBS driver on the CPU with the sound hardware and no LCD. The
+// player stands in for the interrupt handler: it waits for the timer or
+// VBlank and calls the play routine itself.
+pub struct GbsPlayer {
+  gbs: Gbs,
+  pub cpu: Cpu,
+  pub memory: Memory,
+  // CPU cycle at which a VBlank-driven driver is called next
+  next_frame: u64,
+}
+
+impl GbsPlayer {
+  pub fn new(gbs: Gbs) -> Self {
+    Self {
+      gbs,
+      cpu: Cpu::new(),
+      memory: Memory::new(),
+      next_frame: 0,
+    }
+  }
+
+  // Resets the machine and runs the init routine for a 1-based song number.
+  // VGM and MIDI logs that were started carry on through the reset.
+  pub fn start_song(&mut self, song: u8) -> Result<()> {
+    if song == 0 || song > self.gbs.song_count {
+      let message = format!("No track {}, the file has tracks 1 to {}", song, self.gbs.song_count);
+      return Err(Error::new(ErrorKind::InvalidInput, message));
+    }
+
+    let rom = self.gbs.rom();
+    let cartridge = Cartridge::with_mbc(self.gbs.title.clone(), rom, Box::new(GbsMapper::new()));
+    let vgm = self.memory.vgm.take();
+    let midi = self.memory.midi.take();
+
+    self.cpu = Cpu::new();
+    self.memory = Memory::new();
+    self.memory.vgm = vgm;
+    self.memory.midi = midi;
+    self.memory.load_cartridge(cartridge);
+    self.memory.ppu.lcdc = 0x00;
+
+    if self.gbs.double_speed() {
+      self.memory.double_speed = true;
+      self.memory.timer.double_speed = true;
+    }
+
+    self.memory.write(0xFF26, 0x80);
+    self.memory.write(0xFF25, 0xFF);
+    self.memory.write(0xFF24, 0x77);
+
+    self.memory.write(0xFF06, self.gbs.timer_modulo);
+    self.memory.write(0xFF05, self.gbs.timer_modulo);
+    self.memory.write(0xFF07, self.gbs.timer_control & 0x07);
+
+    self.cpu.registers.sp = self.gbs.stack_pointer;
+    self.cpu.registers.a = song - 1;
+    self.call(self.gbs.init_address)?;
+
+    self.next_frame = self.cpu.cycles + self.frame_cycles();
+
+    Ok(())
+  }
+
+  // Plays for a number of emulated seconds, handing the machine over after
+  // every play call to take the audio from, e.g. into an AudioSink
+  pub fn render<F: FnMut(&mut Memory)>(&mut self, seconds: u64, mut output: F) -> Result<()> {
+    let end = self.cpu.cycles + seconds * self.cpu_clock();
+
+    while self.wait_for_play(end) {
+      self.call(self.gbs.play_address)?;
+      output(&mut self.memory);
+    }
+    output(&mut self.memory);
+
+    Ok(())
+  }
+
+  // Idles until play is due, returning false when the CPU cycle end comes
+  // first
+  fn wait_for_play(&mut self, end: u64) -> bool {
+    if self.gbs.uses_timer() {
+      while self.memory.read(IF_ADDRESS) & TIMER_INTERRUPT == 0 {
+        if self.cpu.cycles >= end {
+          return false;
+        }
+        self.idle();
+      }
+
+      let interrupts = self.memory.read(IF_ADDRESS);
+      self.memory.write(IF_ADDRESS, interrupts & !TIMER_INTERRUPT);
+    } else {
+      while self.cpu.cycles < self.next_frame {
+        if self.cpu.cycles >= end {
+          return false;
+        }
+        self.idle();
+      }
+
+      self.next_frame += self.frame_cycles();
+    }
+
+    true
+  }
+
+  // The CPU clock, twice the master clock in double speed
+  fn cpu_clock(&self) -> u64 {
+    MASTER_CLOCK_SPEED as u64 * if self.gbs.double_speed() { 2 } else { 1 }
+  }
+
+  fn frame_cycles(&self) -> u64 {
+    FRAME_CYCLES * if self.gbs.double_speed() { 2 } else { 1 }
+  }
+
+  // One M-cycle of the CPU halted between calls
+  fn idle(&mut self) {
+    self.cpu.cycles += 4;
+    self.memory.tick(4);
+  }
+
+  // Calls a routine the way CALL would, running it until it returns
+  fn call(&mut self, address: u16) -> Result<()> {
+    let [low, high] = RETURN_ADDRESS.to_le_bytes();
+    let sp = self.cpu.registers.sp.wrapping_sub(2);
+    self.memory.write(sp, low);
+    self.memory.write(sp.wrapping_add(1), high);
+
+    self.cpu.registers.sp = sp;
+    self.cpu.registers.pc = address;
+
+    let timeout = self.cpu.cycles + CALL_TIMEOUT;
+    while self.cpu.registers.pc != RETURN_ADDRESS {
+      if self.cpu.cycles >= timeout {
+        return Err(Error::new(ErrorKind::TimedOut, format!("GBS routine at 0x{:04X} did not return", address)));
+      }
+
+      self.cpu.run_instruction(&mut self.memory);
+    }
+
+    Ok(())
+  }
+}
@@ -0,0 +1,66 @@
+// Infrared port shared by the CGB RP register and cartridges with an IR
+// LED (HuC1, HuC3). Whatever sits on the other side implements InfraredLink.
+
+pub trait InfraredLink {
+  fn set_led(&mut self, on: bool);
+  fn receiving(&self) -> bool;
+}
+
+// Nothing in front of the sensor
+pub struct Disconnected;
+
+impl InfraredLink for Disconnected {
+  fn set_led(&mut self, _on: bool) {}
+
+  fn receiving(&self) -> bool {
+    false
+  }
+}
+
+// A mirror in front of the port: the sensor sees the LED's own light
+pub struct Loopback {
+  light: bool,
+}
+
+impl Loopback {
+  pub fn new() -> Self {
+    Self { light: false }
+  }
+}
+
+impl InfraredLink for Loopback {
+  fn set_led(&mut self, on: bool) {
+    self.light = on;
+  }
+
+  fn receiving(&self) -> bool {
+    self.light
+  }
+}
+
+pub struct Infrared {
+  led_on: bool,
+  link: Box<dyn InfraredLink>,
+}
+
+impl Infrared {
+  pub fn new() -> Self {
+    Self { led_on: false, link: Box::new(Disconnected) }
+  }
+
+  pub fn connect(&mut self, link: Box<dyn InfraredLink>) {
+    self.link = link;
+    self.link.set_led(self.led_on);
+  }
+
+  pub fn set_led(&mut self, on: bool) {
+    if self.led_on != on {
+      self.led_on = on;
+      self.link.set_led(on);
+    }
+  }
+
+  pub fn receiving(&self) -> bool {
+    self.link.receiving()
+  }
+}
@@ -0,0 +1,84 @@
+// P1 at 0xFF00. The eight buttons sit on a 2x4 matrix: writing 0 to bit 4
+// selects the directions, 0 to bit 5 the action buttons, and the low nibble
+// reads 0 for every pressed button on a selected row.
+
+pub const JOYPAD_INTERRUPT: u8 = 0x10;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Button {
+  Right,
+  Left,
+  Up,
+  Down,
+  A,
+  B,
+  Select,
+  Start,
+}
+
+impl Button {
+  // Bit in the pressed byte: directions in the low nibble, buttons above
+  fn mask(self) -> u8 {
+    1 << self as u8
+  }
+}
+
+pub struct Joypad {
+  select: u8, // Bits 4-5 as last written
+  pressed: u8,
+}
+
+impl Joypad {
+  pub fn new() -> Self {
+    Self { select: 0x30, pressed: 0 }
+  }
+
+  pub fn read(&self) -> u8 {
+    0xC0 | self.select | (!self.pressed_lines() & 0x0F)
+  }
+
+  // Returns the interrupts raised by selecting a row with a button held
+  pub fn write(&mut self, value: u8) -> u8 {
+    let previous = self.pressed_lines();
+    self.select = value & 0x30;
+
+    self.interrupts(previous)
+  }
+
+  // Returns the interrupts raised by the change
+  pub fn set_button(&mut self, button: Button, pressed: bool) -> u8 {
+    let previous = self.pressed_lines();
+
+    if pressed {
+      self.pressed |= button.mask();
+    } else {
+      self.pressed &= !button.mask();
+    }
+
+    self.interrupts(previous)
+  }
+
+  // True while a selected line is pulled low, which also ends STOP
+  pub fn any_line_low(&self) -> bool {
+    self.pressed_lines() != 0
+  }
+
+  // Lines pulled low, as set bits
+  fn pressed_lines(&self) -> u8 {
+    let mut lines = 0;
+
+    if self.select & 0x10 == 0 {
+      lines |= self.pressed & 0x0F;
+    }
+    if self.select & 0x20 == 0 {
+      lines |= self.pressed >> 4;
+    }
+
+    lines
+  }
+
+  // The interrupt fires when any line goes from high to low
+  fn interrupts(&self, previous: u8) -> u8 {
+    if self.pressed_lines() & !previous != 0 { JOYPAD_INTERRUPT } else { 0 }
+  }
+}
@@ -1,82 +1,594 @@
 extern crate minifb;
+extern crate png;
+mod apu;
+mod audio;
+mod cartridge;
 mod cpu;
+mod gbs;
+mod helpers;
+mod infrared;
+mod joypad;
 mod memory;
 mod tests;
 mod ppu;
+mod screen;
+mod serial;
+mod timer;
 
-use std::fs::{self, OpenOptions};
+use std::env;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
 use std::io::Result;
 use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
 
+use apu::midi::MidiLog;
+use audio::audio::AudioSink;
+use audio::audio::NullSink;
+use audio::audio::RingBufferSink;
+use audio::wav::stem_path;
+use audio::wav::WavSink;
+use cartridge::camera_sensor::StillImage;
+use cartridge::cartridge::Cartridge;
 use cpu::cpu::Cpu;
+use cpu::cpu::MASTER_CLOCK_SPEED;
+use gbs::gbs::Gbs;
+use gbs::player::GbsPlayer;
+use infrared::infrared::Loopback;
 use memory::memory::Memory;
+use ppu::ppu::Renderer;
+use screen::screen::Screen;
+use serial::link::link_cable;
+use serial::printer::GameBoyPrinter;
+use serial::serial::Disconnected;
+use serial::serial::SerialDevice;
+use serial::serial::SerialLogger;
 
-fn load_rom_file() -> Vec<u8> {
-  let rom = fs::read("roms/06-ld r,r.gb")
+// How long a GBS track plays for when --seconds is not given
+const GBS_DEFAULT_SECONDS: u64 = 120;
+
+// How long a ROM runs for without a window when --seconds is not given
+const HEADLESS_DEFAULT_SECONDS: u64 = 60;
+
+// The window, input and audio are serviced at least this often, in cycles
+// at normal speed, so they keep going while the LCD is off
+const FRAME_CYCLES: u64 = 70224;
+
+// Battery RAM is flushed to disk after this many cycles, when it changed
+const AUTOSAVE_INTERVAL: u64 = 5 * MASTER_CLOCK_SPEED as u64;
+
+struct Options {
+  rom_path: String,
+  camera_image: Option<String>,
+  infrared_loopback: bool,
+  renderer: Renderer,
+  trace: bool,
+  sample_rate: u32,
+  record: Option<String>,
+  stems: bool,
+  headless: bool,
+  seconds: Option<u64>,
+  tilt: (f32, f32),
+  track: Option<u8>,
+  vgm: Option<String>,
+  midi: Option<String>,
+  serial: SerialOption,
+  link: Option<String>,
+}
+
+// What is plugged into the link port
+enum SerialOption {
+  None,
+  Log,
+  Printer,
+}
+
+// The mixed output, and each channel on its own when recording stems.
+// Headless runs also keep the last second of the mix, to report on.
+struct AudioOutputs {
+  mix: Box<dyn AudioSink>,
+  stems: Vec<Box<dyn AudioSink>>,
+  tail: Option<RingBufferSink>,
+}
+
+// clonelebi [rom] [--camera-image <png or pgm>] [--infrared <none or loopback>]
+//   [--ppu <scanline or fifo>]
+//   [--trace] [--sample-rate <Hz>] [--record <wav> [--stems]] [--headless]
+//   [--seconds <emulated seconds to run for>] [--track <GBS song number>]
+//   [--tilt <x>,<y> (constant tilt in g, added to the IJKL keys)]
+//   [--vgm <file to log sound register writes to>]
+//   [--midi <file to transcribe the notes played to>]
+//   [--serial <log, printer or none>]
+//   [--link <ROM for a second Game Boy on the other end of a link cable>]
+//
+// A .gbs file instead of a ROM plays its music headlessly. Bytes sent over
+// the link port are written to stdout unless --serial says otherwise, and
+// the printer saves each print as print-<n>.png in the working directory.
+// --link runs the second Game Boy headless in step with the first, taking
+// the place of the --serial device, and saving to <rom>-link.sav when it
+// runs the same ROM. Headless runs stop after
+// HEADLESS_DEFAULT_SECONDS when --seconds is not given, and say how loud
+// their last second of audio was.
+fn parse_options() -> Options {
+  let mut options = Options {
+    rom_path: String::from("roms/06-ld r,r.gb"),
+    camera_image: None,
+    infrared_loopback: false,
+    renderer: Renderer::Scanline,
+    trace: false,
+    sample_rate: 44100,
+    record: None,
+    stems: false,
+    headless: false,
+    seconds: None,
+    tilt: (0.0, 0.0),
+    track: None,
+    vgm: None,
+    midi: None,
+    serial: SerialOption::Log,
+    link: None,
+  };
+
+  let mut args = env::args().skip(1);
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--camera-image" => options.camera_image = args.next(),
+      "--infrared" => {
+        options.infrared_loopback = match args.next().as_deref() {
+          Some("loopback") => true,
+          Some("none") => false,
+          other => panic!("Unknown infrared link: {:?}", other),
+        }
+      },
+      "--trace" => options.trace = true,
+      "--sample-rate" => {
+        options.sample_rate = args.next()
+          .and_then(|rate| rate.parse().ok())
+          .expect("--sample-rate takes a rate in Hz");
+      },
+      "--record" => options.record = args.next(),
+      "--stems" => options.stems = true,
+      "--vgm" => options.vgm = args.next(),
+      "--midi" => options.midi = args.next(),
+      "--headless" => options.headless = true,
+      "--seconds" => {
+        options.seconds = Some(args.next()
+          .and_then(|seconds| seconds.parse().ok())
+          .expect("--seconds takes a whole number of seconds"));
+      },
+      "--tilt" => {
+        options.tilt = args.next()
+          .and_then(|tilt| {
+            let (x, y) = tilt.split_once(',')?;
+            Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+          })
+          .expect("--tilt takes x and y in g, e.g. 0.5,-0.25");
+      },
+      "--track" => {
+        options.track = Some(args.next()
+          .and_then(|track| track.parse().ok())
+          .expect("--track takes a song number, starting at 1"));
+      },
+      "--serial" => {
+        options.serial = match args.next().as_deref() {
+          Some("log") => SerialOption::Log,
+          Some("printer") => SerialOption::Printer,
+          Some("none") => SerialOption::None,
+          other => panic!("Unknown serial device: {:?}", other),
+        }
+      },
+      "--link" => options.link = args.next(),
+      "--ppu" => {
+        options.renderer = match args.next().as_deref() {
+          Some("fifo") => Renderer::Fifo,
+          Some("scanline") => Renderer::Scanline,
+          other => panic!("Unknown PPU renderer: {:?}", other),
+        }
+      },
+      _ => options.rom_path = arg,
+    }
+  }
+
+  if options.stems && options.record.is_none() {
+    panic!("--stems needs --record to name the files");
+  }
+
+  options
+}
+
+fn load_cartridge(options: &Options) -> Cartridge {
+  let mut cartridge = Cartridge::from_file(Path::new(&options.rom_path))
     .expect("Should have been able to read the file");
 
-  if rom.is_empty() {
+  if cartridge.is_empty() {
     panic!("Could not load ROM");
   }
 
-  rom
+  if let Some(camera_image) = &options.camera_image {
+    let image = StillImage::from_file(Path::new(camera_image))
+      .expect("Should have been able to read the camera image");
+    cartridge.set_camera_sensor(Box::new(image));
+  }
+
+  if options.infrared_loopback {
+    cartridge.connect_infrared(Box::new(Loopback::new()));
+  }
+
+  cartridge
+}
+
+fn serial_device(options: &Options) -> Box<dyn SerialDevice> {
+  match options.serial {
+    SerialOption::None => Box::new(Disconnected),
+    SerialOption::Log => Box::new(SerialLogger::new(io::stdout())),
+    SerialOption::Printer => Box::new(GameBoyPrinter::new(Path::new("."))),
+  }
+}
+
+// Initial setup, as the boot ROM leaves it. CGB games look for A = 0x11
+// to tell they run on a CGB.
+fn boot(cpu: &mut Cpu, memory: &mut Memory) {
+  if memory.ppu.cgb {
+    cpu.registers.a = 0x11;
+    cpu.registers.f = 0x80;
+    cpu.registers.b = 0x00;
+    cpu.registers.c = 0x00;
+    cpu.registers.d = 0xFF;
+    cpu.registers.e = 0x56;
+    cpu.registers.h = 0x00;
+    cpu.registers.l = 0x0D;
+  } else {
+    cpu.registers.a = 0x01;
+    cpu.registers.f = 0xB0;
+    cpu.registers.b = 0x00;
+    cpu.registers.c = 0x13;
+    cpu.registers.d = 0x00;
+    cpu.registers.e = 0xD8;
+    cpu.registers.h = 0x01;
+    cpu.registers.l = 0x4D;
+  }
+  cpu.registers.sp = 0xFFFE;
+  cpu.registers.pc = 0x0100;
+  memory.timer.divider = 0xABCC;
+}
+
+// A second Game Boy, booted with its own ROM, on the other end of a link
+// cable from the first. Linking a ROM to itself would have both sides
+// share one .sav, so the second saves to <rom>-link.sav instead.
+fn link_game_boy(rom_path: &str, options: &Options, memory: &mut Memory) -> (Cpu, Memory) {
+  let path = Path::new(rom_path);
+  let save_base = if same_file(path, Path::new(&options.rom_path)) {
+    linked_save_base(path)
+  } else {
+    path.to_path_buf()
+  };
+
+  let cartridge = Cartridge::from_file_saving_to(path, &save_base)
+    .expect("Should have been able to read the linked ROM");
+
+  let (port, other_port) = link_cable();
+  memory.serial.connect(Box::new(port));
+
+  let mut linked_cpu = Cpu::new();
+  let mut linked_memory = Memory::new();
+  linked_memory.load_cartridge(cartridge);
+  linked_memory.serial.connect(Box::new(other_port));
+  boot(&mut linked_cpu, &mut linked_memory);
+
+  (linked_cpu, linked_memory)
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+  match (fs::canonicalize(a), fs::canonicalize(b)) {
+    (Ok(a), Ok(b)) => a == b,
+    _ => a == b,
+  }
+}
+
+// game.gb -> game-link, which saves as game-link.sav
+fn linked_save_base(path: &Path) -> PathBuf {
+  let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+  path.with_file_name(format!("{}-link", stem))
 }
 
 fn main() {
-  let rom = load_rom_file();
+  let options = parse_options();
+
+  if options.rom_path.to_lowercase().ends_with(".gbs") {
+    play_gbs(&options);
+    return;
+  }
+
+  let cartridge = load_cartridge(&options);
 
   let mut cpu: Cpu = Cpu::new();
   let mut memory: Memory = Memory::new();
+  memory.ppu.renderer = options.renderer;
 
-  memory.load_rom_into_memory(&rom);
-
-  // Initial setup
-  cpu.registers.a = 0x01;
-  cpu.registers.f = 0xB0;
-  cpu.registers.b = 0x00;
-  cpu.registers.c = 0x13;
-  cpu.registers.d = 0x00;
-  cpu.registers.e = 0xD8;
-  cpu.registers.h = 0x01;
-  cpu.registers.l = 0x4D;
-  cpu.registers.sp = 0xFFFE;
-  cpu.registers.pc = 0x0100;
+  let title = format!("clonelebi - {}", cartridge.title);
+  memory.load_cartridge(cartridge);
+  let mut linked = options.link.as_ref().map(|path| link_game_boy(path, &options, &mut memory));
+  if linked.is_none() {
+    memory.serial.connect(serial_device(&options));
+  }
+  if options.infrared_loopback {
+    memory.infrared.connect(Box::new(Loopback::new()));
+  }
+  let mut screen = if options.headless { None } else { Screen::new(&title) };
+  let mut audio = open_audio(&options, screen.is_none(), &mut memory);
+
+  start_logs(&options, &mut memory);
+
+  boot(&mut cpu, &mut memory);
+
+  let mut next_service = FRAME_CYCLES;
+  let mut next_autosave = AUTOSAVE_INTERVAL;
+  let seconds = match (options.seconds, &screen) {
+    (None, None) => Some(HEADLESS_DEFAULT_SECONDS),
+    (seconds, _) => seconds,
+  };
+  let cycle_limit = seconds.map(|seconds| seconds * MASTER_CLOCK_SPEED as u64);
 
-  let serial_output_address = 0xFF02;
-  let mut serial_output_value = memory.read(serial_output_address);
-
-  while serial_output_value != 0x81 {
-    serial_output_value = memory.read(serial_output_address);
-
-    println!("PC: {:X}", cpu.registers.pc);
-
-    let contents = format!("A:{A:02X} F:{F:02X} B:{B:02X} C:{C:02X} D:{D:02X} E:{E:02X} H:{H:02X} L:{L:02X} SP:{SP:04X} PC:{PC:04X} PCMEM:{PCMEM0:02X},{PCMEM1:02X},{PCMEM2:02X},{PCMEM3:02X}\n",
-      A = cpu.registers.a,
-      F = cpu.registers.f,
-      B = cpu.registers.b,
-      C = cpu.registers.c,
-      D = cpu.registers.d,
-      E = cpu.registers.e,
-      H = cpu.registers.h,
-      L = cpu.registers.l,
-      SP = cpu.registers.sp,
-      PC = cpu.registers.pc,
-      PCMEM0 = memory.read(cpu.registers.pc),
-      PCMEM1 = memory.read(cpu.registers.pc + 1),
-      PCMEM2 = memory.read(cpu.registers.pc + 2),
-      PCMEM3 = memory.read(cpu.registers.pc + 3),
-    );
-
-    match append_to_file("./logs/cpu_log.txt", &contents) {
-        Ok(_) => {},
-        Err(e) => println!("Error appending to file: {}", e),
+  loop {
+    if options.trace {
+      trace(&cpu, &memory);
     }
 
     cpu.run_instruction(&mut memory);
+
+    // The other Game Boy catches up, keeping the cable well within a bit
+    if let Some((linked_cpu, linked_memory)) = &mut linked {
+      while linked_cpu.cycles < cpu.cycles && !linked_cpu.stopped {
+        linked_cpu.run_instruction(linked_memory);
+      }
+    }
+
+    // Once a frame, or a frame's worth of cycles when none comes
+    let frame_ready = memory.ppu.frame_ready;
+    if frame_ready || cpu.cycles >= next_service {
+      memory.ppu.frame_ready = false;
+      next_service = cpu.cycles + FRAME_CYCLES * if memory.double_speed { 2 } else { 1 };
+
+      write_audio(&mut audio, &mut memory);
+      read_tilt(&options, screen.as_ref(), &mut memory);
+      report_speaker(&mut memory);
+
+      if let Some(screen) = &mut screen {
+        if !screen.is_open() {
+          break;
+        }
+
+        if frame_ready {
+          screen.update(memory.ppu.framebuffer());
+        } else {
+          screen.poll();
+        }
+        read_buttons(screen, &mut memory);
+      }
+    }
+
+    // No frames come in while the CPU is stopped, keep the window alive
+    // until a button wakes it
+    if cpu.stopped {
+      match &mut screen {
+        Some(screen) if screen.is_open() => {
+          screen.poll();
+          read_buttons(screen, &mut memory);
+          thread::sleep(Duration::from_millis(16));
+        },
+        _ => {
+          println!("CPU stopped with no input to wake it");
+          break;
+        },
+      }
+    }
+
+    if cpu.cycles >= next_autosave {
+      next_autosave = cpu.cycles + AUTOSAVE_INTERVAL;
+      flush_cartridge(&mut memory);
+      if let Some((_, linked_memory)) = &mut linked {
+        flush_cartridge(linked_memory);
+      }
+    }
+
+    if cycle_limit.is_some_and(|limit| cpu.cycles >= limit) {
+      break;
+    }
+  }
+
+  write_audio(&mut audio, &mut memory);
+  finish_audio(&mut audio);
+  save_logs(&options, &mut memory);
+
+  flush_cartridge(&mut memory);
+  if let Some((_, linked_memory)) = &mut linked {
+    flush_cartridge(linked_memory);
+  }
+}
+
+fn play_gbs(options: &Options) {
+  let gbs = Gbs::from_file(Path::new(&options.rom_path))
+    .expect("Should have been able to read the GBS file");
+  let track = options.track.unwrap_or(gbs.first_song);
+
+  println!("{} - {} ({})", gbs.title, gbs.author, gbs.copyright);
+  println!("Track {} of {}", track, gbs.song_count);
+
+  let mut player = GbsPlayer::new(gbs);
+  start_logs(options, &mut player.memory);
+
+  if let Err(e) = player.start_song(track) {
+    println!("Error starting GBS track: {}", e);
+    return;
+  }
+
+  let mut audio = open_audio(options, true, &mut player.memory);
+  let seconds = options.seconds.unwrap_or(GBS_DEFAULT_SECONDS);
+
+  if let Err(e) = player.render(seconds, |memory| write_audio(&mut audio, memory)) {
+    println!("Error playing GBS track: {}", e);
+  }
+
+  finish_audio(&mut audio);
+  save_logs(options, &mut player.memory);
+}
+
+// Records to WAV files when asked to, otherwise the audio is resampled into
+// nothing as there is no sound card output yet
+fn open_audio(options: &Options, headless: bool, memory: &mut Memory) -> AudioOutputs {
+  memory.apu.set_sample_rate(options.sample_rate);
+
+  let tail = if headless { Some(RingBufferSink::new(options.sample_rate as usize)) } else { None };
+
+  let path = match &options.record {
+    Some(path) => Path::new(path),
+    None => return AudioOutputs { mix: Box::new(NullSink), stems: Vec::new(), tail },
+  };
+
+  let create = |path: &Path, channels| -> Box<dyn AudioSink> {
+    Box::new(WavSink::create(path, options.sample_rate, channels)
+      .expect("Should have been able to create the WAV file"))
+  };
+
+  let mut stems = Vec::new();
+  if options.stems {
+    memory.apu.enable_stems(options.sample_rate);
+    stems = (1..=4).map(|channel| create(&stem_path(path, channel), 2)).collect();
   }
 
-  if serial_output_value == 0x81 {
-    dbg!(memory.read(serial_output_address));
+  AudioOutputs { mix: create(path, 2), stems, tail }
+}
+
+fn write_audio(audio: &mut AudioOutputs, memory: &mut Memory) {
+  let samples = memory.apu.take_samples();
+  let mut result = audio.mix.write(&samples);
+
+  if let Some(tail) = &mut audio.tail {
+    result = result.and(tail.write(&samples));
+  }
+
+  for (stem, samples) in audio.stems.iter_mut().zip(memory.apu.take_stem_samples()) {
+    result = result.and(stem.write(&samples));
+  }
+
+  if let Err(e) = result {
+    println!("Error writing audio: {}", e);
+  }
+}
+
+fn finish_audio(audio: &mut AudioOutputs) {
+  for sink in std::iter::once(&mut audio.mix).chain(audio.stems.iter_mut()) {
+    if let Err(e) = sink.finish() {
+      println!("Error finishing audio output: {}", e);
+    }
+  }
+
+  if let Some(tail) = &mut audio.tail {
+    report_audio_tail(tail);
+  }
+}
+
+// Tells whether a headless run ended on sound, and how loud
+fn report_audio_tail(tail: &mut RingBufferSink) {
+  if tail.is_empty() {
+    println!("No audio was produced");
+    return;
+  }
+
+  let mut samples = vec![(0.0, 0.0); tail.len()];
+  tail.read(&mut samples);
+
+  let peak = samples.iter().fold(0.0f32, |peak, &(left, right)| peak.max(left.abs()).max(right.abs()));
+  if peak == 0.0 {
+    println!("The last second of audio is silent");
+  } else {
+    println!("The last second of audio peaks at {:.1} dBFS", 20.0 * peak.log10());
+  }
+}
+
+fn start_logs(options: &Options, memory: &mut Memory) {
+  if options.vgm.is_some() {
+    memory.start_vgm_log();
+  }
+
+  if options.midi.is_some() {
+    memory.midi = Some(MidiLog::new());
+  }
+}
+
+fn save_logs(options: &Options, memory: &mut Memory) {
+  if let (Some(path), Some(vgm)) = (&options.vgm, &mut memory.vgm) {
+    if let Err(e) = fs::write(path, vgm.bytes()) {
+      println!("Error writing VGM file: {}", e);
+    }
+  }
+
+  if let (Some(path), Some(midi)) = (&options.midi, &mut memory.midi) {
+    if let Err(e) = fs::write(path, midi.bytes()) {
+      println!("Error writing MIDI file: {}", e);
+    }
+  }
+}
+
+fn read_buttons(screen: &Screen, memory: &mut Memory) {
+  for (button, pressed) in screen.buttons() {
+    memory.set_button(button, pressed);
+  }
+}
+
+// Feeds the accelerometer once a frame, from --tilt and the tilt keys
+fn read_tilt(options: &Options, screen: Option<&Screen>, memory: &mut Memory) {
+  let (x, y) = screen.map_or((0.0, 0.0), |screen| screen.tilt());
+
+  if let Some(cartridge) = memory.cartridge_mut() {
+    cartridge.set_tilt(options.tilt.0 + x, options.tilt.1 + y);
+  }
+}
+
+// Cartridges with their own speaker play tones the APU does not mix in,
+// so they are reported instead
+fn report_speaker(memory: &mut Memory) {
+  if let Some(tone) = memory.cartridge_mut().and_then(|cartridge| cartridge.take_speaker_tone()) {
+    println!("Cartridge speaker tone: {:X}", tone);
+  }
+}
+
+// Prints PC and logs the CPU state before each instruction
+fn trace(cpu: &Cpu, memory: &Memory) {
+  println!("PC: {:X}", cpu.registers.pc);
+
+  let contents = format!("A:{A:02X} F:{F:02X} B:{B:02X} C:{C:02X} D:{D:02X} E:{E:02X} H:{H:02X} L:{L:02X} SP:{SP:04X} PC:{PC:04X} PCMEM:{PCMEM0:02X},{PCMEM1:02X},{PCMEM2:02X},{PCMEM3:02X}\n",
+    A = cpu.registers.a,
+    F = cpu.registers.f,
+    B = cpu.registers.b,
+    C = cpu.registers.c,
+    D = cpu.registers.d,
+    E = cpu.registers.e,
+    H = cpu.registers.h,
+    L = cpu.registers.l,
+    SP = cpu.registers.sp,
+    PC = cpu.registers.pc,
+    PCMEM0 = memory.read(cpu.registers.pc),
+    PCMEM1 = memory.read(cpu.registers.pc + 1),
+    PCMEM2 = memory.read(cpu.registers.pc + 2),
+    PCMEM3 = memory.read(cpu.registers.pc + 3),
+  );
+
+  match append_to_file("./logs/cpu_log.txt", &contents) {
+      Ok(_) => {},
+      Err(e) => println!("Error appending to file: {}", e),
+  }
+}
+
+fn flush_cartridge(memory: &mut Memory) {
+  if let Some(cartridge) = memory.cartridge_mut() {
+    if let Err(e) = cartridge.flush() {
+      println!("Error saving cartridge data: {}", e);
+    }
   }
 }
 
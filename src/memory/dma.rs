@@ -0,0 +1,207 @@
+pub const OAM_DMA_LENGTH: usize = 0xA0;
+pub const HDMA_BLOCK_LENGTH: u16 = 0x10;
+
+// CPU cycles a 16-byte VRAM DMA block halts the CPU for. The transfer runs
+// at the same real speed in double-speed mode, so it takes twice the cycles.
+pub fn hdma_block_cycles(double_speed: bool) -> u64 {
+  if double_speed { 64 } else { 32 }
+}
+
+// OAM DMA, started by writing the source page to 0xFF46. After a 1 M-cycle
+// setup it copies one byte per M-cycle into OAM, and while it runs the CPU
+// only has HRAM and the I/O registers to itself.
+pub struct OamDma {
+  pub register: u8,
+  source: u16,
+  index: usize,
+  delay: u8,
+  active: bool,
+  cycles: u64,
+  // The byte on the bus being copied, what the CPU sees on conflicting reads
+  pub current_byte: u8,
+}
+
+impl OamDma {
+  pub fn new() -> Self {
+    Self {
+      register: 0xFF,
+      source: 0,
+      index: 0,
+      delay: 0,
+      active: false,
+      cycles: 0,
+      current_byte: 0xFF,
+    }
+  }
+
+  pub fn start(&mut self, value: u8) {
+    self.register = value;
+    // Pages 0xE0-0xFF read the echo of work RAM
+    self.source = if value >= 0xE0 { (value as u16 - 0x20) << 8 } else { (value as u16) << 8 };
+    self.index = 0;
+    self.delay = 1;
+    self.active = true;
+  }
+
+  // True while bytes are being copied, when the CPU bus is taken
+  pub fn is_transferring(&self) -> bool {
+    self.active && self.delay == 0
+  }
+
+  // Adds T-cycles, returning how many M-cycles the transfer can run for
+  pub fn advance(&mut self, cycles: u64) -> u64 {
+    if !self.active {
+      self.cycles = 0;
+      return 0;
+    }
+
+    self.cycles += cycles;
+    let m_cycles = self.cycles / 4;
+    self.cycles %= 4;
+
+    m_cycles
+  }
+
+  // Runs one M-cycle, returning the source address and OAM index of the byte
+  // to copy on it
+  pub fn step(&mut self) -> Option<(u16, usize)> {
+    if !self.active {
+      return None;
+    }
+
+    if self.delay > 0 {
+      self.delay -= 1;
+      return None;
+    }
+
+    let transfer = (self.source + self.index as u16, self.index);
+
+    self.index += 1;
+    if self.index == OAM_DMA_LENGTH {
+      self.active = false;
+    }
+
+    Some(transfer)
+  }
+
+  // What a CPU read sees during a transfer: HRAM and I/O are untouched, the
+  // bus the source sits on returns the byte in flight, anything else 0xFF
+  pub fn conflicting_read(&self, address: u16) -> Option<u8> {
+    if !self.is_transferring() || address >= 0xFF00 {
+      return None;
+    }
+
+    if bus(address) == bus(self.source) {
+      Some(self.current_byte)
+    } else {
+      Some(0xFF)
+    }
+  }
+
+  pub fn blocks_write(&self, address: u16) -> bool {
+    self.is_transferring() && address < 0xFF00
+  }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Bus {
+  External,
+  Video,
+  Oam,
+}
+
+// VRAM has its own bus, cartridge and work RAM share the external one
+fn bus(address: u16) -> Bus {
+  match address {
+    0x8000..=0x9FFF => Bus::Video,
+    0xFE00..=0xFEFF => Bus::Oam,
+    _ => Bus::External,
+  }
+}
+
+// What a write to 0xFF55 asks for
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HdmaStart {
+  None,
+  General,
+  HBlank,
+}
+
+// CGB VRAM DMA through 0xFF51-0xFF55, copying 16-byte blocks into VRAM
+// either all at once or one block per HBlank
+pub struct Hdma {
+  source: u16,
+  destination: u16,
+  remaining: u8, // Blocks left to copy
+  pub hblank: bool,
+}
+
+impl Hdma {
+  pub fn new() -> Self {
+    Self {
+      source: 0,
+      destination: 0x8000,
+      remaining: 0,
+      hblank: false,
+    }
+  }
+
+  // Only 0xFF55 reads back: the blocks left minus one, bit 7 set once no
+  // HBlank transfer is running, 0xFF after a finished one
+  pub fn read(&self, address: u16) -> u8 {
+    match address {
+      0xFF55 if self.hblank => (self.remaining - 1) & 0x7F,
+      0xFF55 if self.remaining > 0 => 0x80 | ((self.remaining - 1) & 0x7F),
+      _ => 0xFF,
+    }
+  }
+
+  pub fn write(&mut self, address: u16, value: u8) -> HdmaStart {
+    match address {
+      0xFF51 => self.source = (self.source & 0x00F0) | ((value as u16) << 8),
+      0xFF52 => self.source = (self.source & 0xFF00) | (value as u16 & 0xF0),
+      0xFF53 => self.destination = 0x8000 | (self.destination & 0x00F0) | ((value as u16 & 0x1F) << 8),
+      0xFF54 => self.destination = (self.destination & 0xFF00) | (value as u16 & 0xF0),
+      0xFF55 => {
+        // Clearing bit 7 during an HBlank transfer cancels it
+        if self.hblank && value & 0x80 == 0 {
+          self.hblank = false;
+          return HdmaStart::None;
+        }
+
+        self.remaining = (value & 0x7F) + 1;
+        self.hblank = value & 0x80 != 0;
+
+        return if self.hblank { HdmaStart::HBlank } else { HdmaStart::General };
+      },
+      _ => {},
+    }
+
+    HdmaStart::None
+  }
+
+  // Source and destination of the next block, advancing past it. Running
+  // off the end of VRAM ends the transfer.
+  pub fn next_block(&mut self) -> Option<(u16, u16)> {
+    if self.remaining == 0 {
+      return None;
+    }
+
+    let block = (self.source, self.destination);
+
+    self.source = self.source.wrapping_add(HDMA_BLOCK_LENGTH);
+    self.destination += HDMA_BLOCK_LENGTH;
+    self.remaining -= 1;
+
+    if self.destination > 0x9FFF {
+      self.destination = 0x8000;
+      self.remaining = 0;
+    }
+
+    if self.remaining == 0 {
+      self.hblank = false;
+    }
+
+    Some(block)
+  }
+}
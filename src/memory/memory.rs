@@ -1,28 +1,360 @@
+use apu::apu::Apu;
+use apu::midi::MidiLog;
+use apu::vgm::VgmLog;
+use cartridge::cartridge::Cartridge;
+use infrared::infrared::Infrared;
+use joypad::joypad::Button;
+use joypad::joypad::Joypad;
+use memory::dma::hdma_block_cycles;
+use memory::dma::Hdma;
+use memory::dma::HdmaStart;
+use memory::dma::OamDma;
+use memory::dma::HDMA_BLOCK_LENGTH;
+use ppu::ppu::Mode;
+use ppu::ppu::Ppu;
+use serial::serial::Serial;
+use timer::timer::Timer;
+
+const P1_ADDRESS: u16 = 0xFF00;
+const IF_ADDRESS: u16 = 0xFF0F;
+const KEY1_ADDRESS: u16 = 0xFF4D;
+const SVBK_ADDRESS: u16 = 0xFF70;
+const OAM_DMA_ADDRESS: u16 = 0xFF46;
+const RP_ADDRESS: u16 = 0xFF56;
+
+// Eight 4 KiB banks on CGB, of which DMG only has the first two
+const WRAM_BANK_SIZE: usize = 0x1000;
+const WRAM_SIZE: usize = WRAM_BANK_SIZE * 8;
+
+// CPU cycles, 2050 M-cycles, the CPU sits still while a speed switch settles
+const SPEED_SWITCH_CYCLES: u64 = 2050 * 4;
+
 pub struct Memory {
-  memory: [u8; 65536] // 64 KiB of memory
+  memory: [u8; 65536], // 64 KiB of memory
+  cartridge: Option<Cartridge>,
+  wram: [u8; WRAM_SIZE],
+  wram_bank: u8, // SVBK, bank 0 selecting bank 1
+  pub infrared: Infrared,
+  pub joypad: Joypad,
+  pub serial: Serial,
+  pub ppu: Ppu,
+  pub timer: Timer,
+  pub apu: Apu,
+  // Sound register writes, when logging them
+  pub vgm: Option<VgmLog>,
+  // Notes played, when transcribing them to MIDI
+  pub midi: Option<MidiLog>,
+  pub oam_dma: OamDma,
+  pub hdma: Hdma,
+  // CGB double-speed mode, where the CPU runs at twice the clock, switched
+  // by STOP once KEY1 bit 0 asked for it
+  pub double_speed: bool,
+  speed_switch_armed: bool,
+  // Cycles the CPU has to sit out, for DMA that halts it
+  stall_cycles: u64,
 }
 
 impl Memory {
   pub fn new() -> Self {
-    Self { memory: [0; 65536] }
+    Self {
+      memory: [0; 65536],
+      cartridge: None,
+      wram: [0; WRAM_SIZE],
+      wram_bank: 0,
+      infrared: Infrared::new(),
+      joypad: Joypad::new(),
+      serial: Serial::new(),
+      ppu: Ppu::new(),
+      timer: Timer::new(),
+      apu: Apu::new(),
+      vgm: None,
+      midi: None,
+      oam_dma: OamDma::new(),
+      hdma: Hdma::new(),
+      double_speed: false,
+      speed_switch_armed: false,
+      stall_cycles: 0,
+    }
   }
 
   pub fn read(&self, address: u16) -> u8 {
-    // Hard-coded for GameBoy Doctor
-    if address == 0xFF44 {
-      return 0x90;
+    if let Some(value) = self.oam_dma.conflicting_read(address) {
+      return value;
+    }
+
+    self.read_bus(address)
+  }
+
+  // A read as seen by the DMA engines, free of their own bus conflicts
+  fn read_bus(&self, address: u16) -> u8 {
+    if let Some(cartridge) = &self.cartridge {
+      if let 0x0000..=0x7FFF | 0xA000..=0xBFFF = address {
+        return cartridge.read(address);
+      }
     }
+
+    match address {
+      0x8000..=0x9FFF => return self.ppu.read_vram(address),
+      0xC000..=0xFDFF => return self.wram[self.wram_offset(address)],
+      0xFE00..=0xFE9F => return self.ppu.oam[address as usize - 0xFE00],
+      P1_ADDRESS => return self.joypad.read(),
+      0xFF01 | 0xFF02 => return self.serial.read(address),
+      0xFF04..=0xFF07 => return self.timer.read(address),
+      0xFF10..=0xFF3F => return self.apu.read(address),
+      0xFF76 | 0xFF77 if self.ppu.cgb => return self.apu.read(address),
+      0xFF40..=0xFF45 | 0xFF47..=0xFF4B | 0xFF4F | 0xFF68..=0xFF6B => return self.ppu.read(address),
+      OAM_DMA_ADDRESS => return self.oam_dma.register,
+      0xFF51..=0xFF55 if self.ppu.cgb => return self.hdma.read(address),
+      KEY1_ADDRESS if self.ppu.cgb => {
+        return ((self.double_speed as u8) << 7) | 0x7E | self.speed_switch_armed as u8;
+      },
+      SVBK_ADDRESS if self.ppu.cgb => return 0xF8 | self.wram_bank,
+      RP_ADDRESS => return self.read_rp(),
+      _ => {},
+    }
+
     self.memory[address as usize]
   }
 
   pub fn write(&mut self, address: u16, value: u8) {
+    if self.oam_dma.blocks_write(address) {
+      return;
+    }
+
+    if let Some(cartridge) = &mut self.cartridge {
+      if let 0x0000..=0x7FFF | 0xA000..=0xBFFF = address {
+        cartridge.write(address, value);
+        return;
+      }
+    }
+
+    match address {
+      0x8000..=0x9FFF => {
+        self.ppu.write_vram(address, value);
+        return;
+      },
+      0xC000..=0xFDFF => {
+        self.wram[self.wram_offset(address)] = value;
+        return;
+      },
+      0xFE00..=0xFE9F => {
+        self.ppu.oam[address as usize - 0xFE00] = value;
+        return;
+      },
+      P1_ADDRESS => {
+        let interrupts = self.joypad.write(value);
+        self.request_interrupt(interrupts);
+        return;
+      },
+      0xFF01 | 0xFF02 => {
+        self.serial.write(address, value);
+        return;
+      },
+      0xFF04..=0xFF07 => {
+        self.timer.write(address, value);
+        self.step_frame_sequencer();
+        return;
+      },
+      0xFF10..=0xFF3F => {
+        self.apu.write(address, value);
+
+        if let Some(vgm) = &mut self.vgm {
+          vgm.write(address, value);
+        }
+        if let Some(midi) = &mut self.midi {
+          midi.write(address, value);
+        }
+        return;
+      },
+      0xFF40..=0xFF45 | 0xFF47..=0xFF4B | 0xFF4F | 0xFF68..=0xFF6B => {
+        let interrupts = self.ppu.write(address, value);
+        self.request_interrupt(interrupts);
+        return;
+      },
+      OAM_DMA_ADDRESS => {
+        self.oam_dma.start(value);
+        return;
+      },
+      0xFF51..=0xFF55 if self.ppu.cgb => {
+        let start = self.hdma.write(address, value);
+        self.start_hdma(start);
+        return;
+      },
+      KEY1_ADDRESS if self.ppu.cgb => {
+        self.speed_switch_armed = value & 0x01 != 0;
+        return;
+      },
+      SVBK_ADDRESS if self.ppu.cgb => {
+        self.wram_bank = value & 0x07;
+        return;
+      },
+      RP_ADDRESS => self.infrared.set_led(value & 0x01 != 0),
+      _ => {},
+    }
+
     self.memory[address as usize] = value;
   }
 
-  pub fn load_rom_into_memory(&mut self, rom: &[u8]) {
-    let max_rom_length = 0x8000;
-    let safe_rom_length = rom.len().min(max_rom_length);
-    self.memory[0x0000..0x0000 + safe_rom_length].copy_from_slice(&rom[..safe_rom_length]);
+  // 0xC000-0xCFFF is always bank 0, 0xD000-0xDFFF the bank picked by SVBK,
+  // and 0xE000-0xFDFF echoes both
+  fn wram_offset(&self, address: u16) -> usize {
+    let address = if address >= 0xE000 { address - 0x2000 } else { address } as usize;
+
+    if address < 0xD000 {
+      address - 0xC000
+    } else {
+      self.wram_bank.max(1) as usize * WRAM_BANK_SIZE + (address - 0xD000)
+    }
+  }
+
+  pub fn set_button(&mut self, button: Button, pressed: bool) {
+    let interrupts = self.joypad.set_button(button, pressed);
+    self.request_interrupt(interrupts);
+  }
+
+  // STOP resets DIV, and switches speed when KEY1 was armed, returning
+  // whether it did
+  pub fn stop(&mut self) -> bool {
+    self.timer.write(0xFF04, 0);
+    self.step_frame_sequencer();
+
+    if !self.ppu.cgb || !self.speed_switch_armed {
+      return false;
+    }
+
+    self.double_speed = !self.double_speed;
+    self.timer.double_speed = self.double_speed;
+    self.speed_switch_armed = false;
+    self.stall_cycles += SPEED_SWITCH_CYCLES;
+
+    true
+  }
+
+  // CGB infrared port: bit 0 LED, bit 1 cleared while light is received
+  // (only when reading is enabled through bits 6-7)
+  fn read_rp(&self) -> u8 {
+    let rp = self.memory[RP_ADDRESS as usize] & 0xC1;
+    let read_enabled = rp & 0xC0 == 0xC0;
+
+    if read_enabled && self.infrared.receiving() {
+      rp | 0x3C
+    } else {
+      rp | 0x3E
+    }
+  }
+
+  // Advances by CPU cycles. The timer, serial port and OAM DMA follow the CPU
+  // clock, while the LCD, APU and cartridge keep their rate in double-speed
+  // mode.
+  pub fn tick(&mut self, cycles: u64) {
+    let fixed_cycles = if self.double_speed { cycles / 2 } else { cycles };
+
+    if let Some(cartridge) = &mut self.cartridge {
+      cartridge.tick(fixed_cycles);
+    }
+
+    let interrupts = self.timer.tick(cycles);
+    self.request_interrupt(interrupts);
+    self.step_frame_sequencer();
+
+    let interrupts = self.serial.tick(cycles);
+    self.request_interrupt(interrupts);
+
+    self.tick_oam_dma(cycles);
+    self.apu.tick(fixed_cycles);
+    if let Some(vgm) = &mut self.vgm {
+      vgm.tick(fixed_cycles);
+    }
+    if let Some(midi) = &mut self.midi {
+      midi.tick(fixed_cycles, &self.apu);
+    }
+
+    let interrupts = self.ppu.tick(fixed_cycles);
+    self.request_interrupt(interrupts);
+
+    for _ in 0..self.ppu.take_hblanks() {
+      if self.hdma.hblank {
+        self.copy_hdma_block();
+      }
+    }
+  }
+
+  // Starts logging sound register writes, beginning with the master
+  // registers the APU starts up with so players hear the same
+  pub fn start_vgm_log(&mut self) {
+    let mut vgm = VgmLog::new();
+
+    for address in [0xFF26, 0xFF25, 0xFF24] {
+      vgm.write(address, self.apu.read(address));
+    }
+
+    self.vgm = Some(vgm);
+  }
+
+  // Runs the APU frame sequencer steps the DIV counter has clocked
+  fn step_frame_sequencer(&mut self) {
+    for _ in 0..self.timer.take_frame_clocks() {
+      self.apu.step_frame_sequencer();
+    }
+  }
+
+  // Cycles the CPU must stall for since the last call
+  pub fn take_stall_cycles(&mut self) -> u64 {
+    std::mem::replace(&mut self.stall_cycles, 0)
+  }
+
+  // A general-purpose transfer copies everything at once, an HBlank one
+  // starts right away when already in HBlank or with the LCD off
+  fn start_hdma(&mut self, start: HdmaStart) {
+    match start {
+      HdmaStart::General => while self.copy_hdma_block() {},
+      HdmaStart::HBlank => {
+        if !self.ppu.lcd_enabled() || self.ppu.mode == Mode::HBlank {
+          self.copy_hdma_block();
+        }
+      },
+      HdmaStart::None => {},
+    }
+  }
+
+  fn copy_hdma_block(&mut self) -> bool {
+    let (source, destination) = match self.hdma.next_block() {
+      Some(block) => block,
+      None => return false,
+    };
+
+    for i in 0..HDMA_BLOCK_LENGTH {
+      let value = self.read_bus(source.wrapping_add(i));
+      self.ppu.write_vram(destination + i, value);
+    }
+
+    self.stall_cycles += hdma_block_cycles(self.double_speed);
+    true
+  }
+
+  fn tick_oam_dma(&mut self, cycles: u64) {
+    for _ in 0..self.oam_dma.advance(cycles) {
+      if let Some((source, index)) = self.oam_dma.step() {
+        let value = self.read_bus(source);
+        self.oam_dma.current_byte = value;
+        self.ppu.oam[index] = value;
+      }
+    }
+  }
+
+  pub fn request_interrupt(&mut self, interrupts: u8) {
+    self.memory[IF_ADDRESS as usize] |= interrupts;
+  }
+
+  pub fn load_cartridge(&mut self, cartridge: Cartridge) {
+    self.ppu.cgb = cartridge.cgb;
+    self.apu.cgb = cartridge.cgb;
+    self.serial.cgb = cartridge.cgb;
+    self.cartridge = Some(cartridge);
+  }
+
+  pub fn cartridge_mut(&mut self) -> Option<&mut Cartridge> {
+    self.cartridge.as_mut()
   }
 
   pub fn dump(&mut self, start: u16, end: u16) {
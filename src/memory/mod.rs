@@ -1 +1,2 @@
+pub mod dma;
 pub mod memory;
@@ -0,0 +1,310 @@
+use std::collections::VecDeque;
+
+use ppu::ppu::Ppu;
+use ppu::ppu::Sprite;
+use ppu::ppu::SpritePixel;
+use ppu::ppu::SCREEN_WIDTH;
+
+// Dots spent on the first tile fetch of every line, which is thrown away
+const FIRST_FETCH_DOTS: u8 = 5;
+
+// Dots a sprite fetch stalls the pipeline for, once the background fetcher
+// has a tile ready
+const SPRITE_FETCH_DOTS: u8 = 6;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum FetchStep {
+  TileIndex,
+  DataLow,
+  DataHigh,
+  Push,
+}
+
+// Fetches a row of 8 background or window pixels, each of the first three
+// steps taking 2 dots. Push waits until the background FIFO is empty.
+struct Fetcher {
+  step: FetchStep,
+  dots: u8,
+  tile_x: u8,
+  window: bool,
+  row_offset: usize,
+  attributes: u8,
+  low: u8,
+  high: u8,
+}
+
+impl Fetcher {
+  fn new(window: bool) -> Self {
+    Self {
+      step: FetchStep::TileIndex,
+      dots: 0,
+      tile_x: 0,
+      window,
+      row_offset: 0,
+      attributes: 0,
+      low: 0,
+      high: 0,
+    }
+  }
+}
+
+struct SpriteFetch {
+  sprite: Sprite,
+  dots: u8,
+}
+
+// A pixel leaving the FIFOs for screen column x
+pub struct FifoPixel {
+  pub x: usize,
+  pub background: u8,
+  pub attributes: u8,
+  pub sprite: Option<SpritePixel>,
+}
+
+// Mode 3 as the hardware runs it: a background fetcher feeding a pixel FIFO
+// that shifts out one pixel per dot, stalled by sprite fetches, SCX fine
+// scrolling and window restarts. Registers are read on the dot they are used,
+// so mid-line writes take effect where the hardware would show them.
+pub struct PixelFifo {
+  // Colour indices with the CGB map attributes of their tile
+  background: VecDeque<(u8, u8)>,
+  sprites: VecDeque<Option<SpritePixel>>,
+  fetcher: Fetcher,
+  sprite_fetch: Option<SpriteFetch>,
+  line_sprites: Vec<Sprite>,
+  delay: u8,
+  discard: u8,
+  x: usize,
+  pub window_active: bool,
+}
+
+impl PixelFifo {
+  pub fn new() -> Self {
+    Self {
+      background: VecDeque::with_capacity(16),
+      sprites: VecDeque::with_capacity(8),
+      fetcher: Fetcher::new(false),
+      sprite_fetch: None,
+      line_sprites: Vec::new(),
+      delay: 0,
+      discard: 0,
+      x: 0,
+      window_active: false,
+    }
+  }
+
+  // Called on entering mode 3, after OAM scan picked the line's sprites
+  pub fn start_line(&mut self, ppu: &Ppu) {
+    self.background.clear();
+    self.sprites.clear();
+    self.fetcher = Fetcher::new(false);
+    self.sprite_fetch = None;
+    self.line_sprites = if ppu.lcdc & 0x02 != 0 { ppu.select_sprites() } else { Vec::new() };
+    self.delay = FIRST_FETCH_DOTS;
+    self.discard = ppu.scx % 8;
+    self.x = 0;
+    self.window_active = false;
+  }
+
+  // Mode 3 ends once the last column was pushed to the LCD
+  pub fn is_done(&self) -> bool {
+    self.x >= SCREEN_WIDTH
+  }
+
+  // Runs one dot of mode 3, returning the pixel shifted out on it, if any
+  pub fn step(&mut self, ppu: &Ppu) -> Option<FifoPixel> {
+    if self.is_done() {
+      return None;
+    }
+
+    if self.delay > 0 {
+      self.delay -= 1;
+      return None;
+    }
+
+    if self.sprite_fetch.is_some() {
+      self.step_sprite_fetch(ppu);
+      return None;
+    }
+
+    if self.start_window(ppu) {
+      self.step_fetcher(ppu);
+      return None;
+    }
+
+    if self.start_sprite_fetch() {
+      self.step_sprite_fetch(ppu);
+      return None;
+    }
+
+    let pixel = self.shift_out(ppu);
+    self.step_fetcher(ppu);
+
+    pixel
+  }
+
+  fn shift_out(&mut self, ppu: &Ppu) -> Option<FifoPixel> {
+    let (background, attributes) = self.background.pop_front()?;
+
+    // SCX % 8 pixels of the first tile are dropped, delaying the line
+    if self.discard > 0 {
+      self.discard -= 1;
+      return None;
+    }
+
+    let sprite = self.sprites.pop_front().unwrap_or(None);
+
+    let pixel = FifoPixel {
+      x: self.x,
+      // On DMG, LCDC bit 0 blanks both the background and the window
+      background: if ppu.cgb || ppu.lcdc & 0x01 != 0 { background } else { 0 },
+      attributes,
+      sprite,
+    };
+    self.x += 1;
+
+    Some(pixel)
+  }
+
+  // Reaching WX restarts the fetcher on the window, dropping what was queued.
+  // The SCX % 8 discard no longer applies, but with WX below 7 the window
+  // starts left of the screen and its first 7 - WX pixels are dropped.
+  fn start_window(&mut self, ppu: &Ppu) -> bool {
+    let reached = self.x as i16 + 7 >= ppu.wx as i16;
+
+    if self.window_active || ppu.lcdc & 0x20 == 0 || !ppu.window_triggered || !reached {
+      return false;
+    }
+
+    self.window_active = true;
+    self.discard = 7u8.saturating_sub(ppu.wx);
+    self.background.clear();
+    self.fetcher = Fetcher::new(true);
+
+    true
+  }
+
+  // Sprites are fetched when the pixel about to leave is their leftmost one,
+  // or at column 0 for sprites hanging off the left edge
+  fn start_sprite_fetch(&mut self) -> bool {
+    if self.discard > 0 || self.background.is_empty() {
+      return false;
+    }
+
+    let x = self.x as i16;
+    let position = self.line_sprites.iter().position(|sprite| sprite.x <= x);
+
+    match position {
+      Some(position) => {
+        let sprite = self.line_sprites.remove(position);
+        self.sprite_fetch = Some(SpriteFetch { sprite, dots: SPRITE_FETCH_DOTS });
+        true
+      },
+      None => false,
+    }
+  }
+
+  // The background fetcher finishes its tile first, adding up to 5 dots
+  fn step_sprite_fetch(&mut self, ppu: &Ppu) {
+    if self.fetcher.step != FetchStep::Push {
+      self.step_fetcher(ppu);
+
+      if self.fetcher.step != FetchStep::Push {
+        return;
+      }
+    }
+
+    let finished = match self.sprite_fetch {
+      Some(ref mut fetch) => {
+        fetch.dots -= 1;
+        fetch.dots == 0
+      },
+      None => false,
+    };
+
+    if !finished {
+      return;
+    }
+
+    if let Some(fetch) = self.sprite_fetch.take() {
+      self.merge_sprite(ppu, &fetch.sprite);
+    }
+  }
+
+  // Earlier sprites keep the slots they already hold, so on DMG the lowest X
+  // and then the lowest OAM index wins. On CGB the lowest OAM index wins.
+  fn merge_sprite(&mut self, ppu: &Ppu, sprite: &Sprite) {
+    while self.sprites.len() < 8 {
+      self.sprites.push_back(None);
+    }
+
+    for column in 0..8u8 {
+      let slot = sprite.x + column as i16 - self.x as i16;
+
+      if slot < 0 {
+        continue;
+      }
+
+      let replace = match self.sprites[slot as usize] {
+        Some(held) => ppu.cgb && sprite.index < held.index,
+        None => true,
+      };
+
+      if let (true, Some(pixel)) = (replace, ppu.sprite_fifo_pixel(sprite, column)) {
+        self.sprites[slot as usize] = Some(pixel);
+      }
+    }
+  }
+
+  fn step_fetcher(&mut self, ppu: &Ppu) {
+    let fetcher = &mut self.fetcher;
+
+    if fetcher.step == FetchStep::Push {
+      if !self.background.is_empty() {
+        return;
+      }
+
+      for x in 0..8 {
+        let bit = if fetcher.attributes & 0x20 != 0 { x } else { 7 - x };
+        let colour = (((fetcher.high >> bit) & 0x01) << 1) | ((fetcher.low >> bit) & 0x01);
+        self.background.push_back((colour, fetcher.attributes));
+      }
+
+      fetcher.tile_x = fetcher.tile_x.wrapping_add(1);
+      fetcher.step = FetchStep::TileIndex;
+      return;
+    }
+
+    fetcher.dots += 1;
+    if fetcher.dots < 2 {
+      return;
+    }
+    fetcher.dots = 0;
+
+    match fetcher.step {
+      FetchStep::TileIndex => {
+        let (map, x, y) = if fetcher.window {
+          let map = if ppu.lcdc & 0x40 != 0 { 0x1C00 } else { 0x1800 };
+          (map, fetcher.tile_x, ppu.window_line)
+        } else {
+          let map = if ppu.lcdc & 0x08 != 0 { 0x1C00 } else { 0x1800 };
+          (map, (ppu.scx / 8).wrapping_add(fetcher.tile_x), ppu.ly.wrapping_add(ppu.scy))
+        };
+
+        let entry = map + (y as usize / 8) * 32 + (x as usize % 32);
+        fetcher.attributes = ppu.map_attributes(entry);
+        fetcher.row_offset = ppu.bg_tile_row_offset(ppu.vram[entry], fetcher.attributes, y % 8);
+        fetcher.step = FetchStep::DataLow;
+      },
+      FetchStep::DataLow => {
+        fetcher.low = ppu.vram[fetcher.row_offset];
+        fetcher.step = FetchStep::DataHigh;
+      },
+      FetchStep::DataHigh => {
+        fetcher.high = ppu.vram[fetcher.row_offset + 1];
+        fetcher.step = FetchStep::Push;
+      },
+      FetchStep::Push => {},
+    }
+  }
+}
@@ -0,0 +1,2 @@
+pub mod fifo;
+pub mod ppu;
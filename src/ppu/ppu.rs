@@ -0,0 +1,589 @@
+use ppu::fifo::PixelFifo;
+
+pub const DOTS_PER_LINE: u32 = 456;
+pub const LINES_PER_FRAME: u8 = 154;
+pub const VISIBLE_LINES: u8 = 144;
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+pub const VRAM_SIZE: usize = 0x2000;
+pub const OAM_SIZE: usize = 0xA0;
+pub const PALETTE_RAM_SIZE: usize = 64; // 8 palettes of 4 RGB555 colours
+
+// DMG shades as 0RGB, lightest first
+const DMG_SHADES: [u32; 4] = [0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000];
+
+const MAX_SPRITES_PER_LINE: usize = 10;
+
+const OAM_SCAN_DOTS: u32 = 80;
+const PIXEL_TRANSFER_DOTS: u32 = 172;
+
+// Interrupt flags requested by the PPU, as laid out in IF
+pub const VBLANK_INTERRUPT: u8 = 0x01;
+pub const STAT_INTERRUPT: u8 = 0x02;
+
+// An OAM entry, with Y and X already moved to screen coordinates
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Sprite {
+  pub y: i16,
+  pub x: i16,
+  pub tile: u8,
+  pub attributes: u8,
+  pub index: usize,
+}
+
+// The sprite pixel that won a screen column. On DMG palette holds OBP0 or
+// OBP1, on CGB the number of the OBJ palette.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SpritePixel {
+  pub colour: u8,
+  pub palette: u8,
+  pub behind_background: bool,
+  pub index: usize,
+}
+
+// How mode 3 is emulated: whole lines at the end of a fixed 172 dots, or
+// dot by dot through the pixel FIFO, with a mode 3 length that varies
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Renderer {
+  Scanline,
+  Fifo,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+  HBlank = 0,
+  VBlank = 1,
+  OamScan = 2,
+  PixelTransfer = 3,
+}
+
+pub struct Ppu {
+  pub lcdc: u8,
+  stat: u8, // Only the interrupt enables, bits 3-6
+  pub scy: u8,
+  pub scx: u8,
+  pub ly: u8,
+  pub lyc: u8,
+  pub bgp: u8,
+  pub obp0: u8,
+  pub obp1: u8,
+  pub wy: u8,
+  pub wx: u8,
+  pub mode: Mode,
+  dot: u32,
+  stat_line: bool,
+  // Two banks on CGB, bank 1 holding extra tiles and the BG map attributes
+  pub vram: [u8; VRAM_SIZE * 2],
+  vram_bank: usize,
+  pub oam: [u8; OAM_SIZE],
+  pub cgb: bool,
+  bcps: u8,
+  pub bg_palettes: [u8; PALETTE_RAM_SIZE],
+  ocps: u8,
+  pub obj_palettes: [u8; PALETTE_RAM_SIZE],
+  framebuffer: Vec<u32>,
+  pub frame_ready: bool,
+  // HBlanks entered since the last take_hblanks, for HBlank DMA
+  hblanks: u32,
+  // Colour indices and CGB map attributes of the background and window on
+  // the current line
+  bg_line: [u8; SCREEN_WIDTH],
+  bg_attributes: [u8; SCREEN_WIDTH],
+  // The window keeps its own line counter, only advanced on lines it was drawn
+  pub window_line: u8,
+  pub window_triggered: bool,
+  pub renderer: Renderer,
+  fifo: PixelFifo,
+}
+
+impl Ppu {
+  pub fn new() -> Self {
+    Self {
+      lcdc: 0x91,
+      stat: 0x00,
+      scy: 0,
+      scx: 0,
+      ly: 0,
+      lyc: 0,
+      bgp: 0xFC,
+      obp0: 0xFF,
+      obp1: 0xFF,
+      wy: 0,
+      wx: 0,
+      mode: Mode::OamScan,
+      dot: 0,
+      stat_line: false,
+      vram: [0; VRAM_SIZE * 2],
+      vram_bank: 0,
+      oam: [0; OAM_SIZE],
+      cgb: false,
+      bcps: 0,
+      bg_palettes: [0xFF; PALETTE_RAM_SIZE],
+      ocps: 0,
+      obj_palettes: [0xFF; PALETTE_RAM_SIZE],
+      framebuffer: vec![DMG_SHADES[0]; SCREEN_WIDTH * SCREEN_HEIGHT],
+      frame_ready: false,
+      hblanks: 0,
+      bg_line: [0; SCREEN_WIDTH],
+      bg_attributes: [0; SCREEN_WIDTH],
+      window_line: 0,
+      window_triggered: false,
+      renderer: Renderer::Scanline,
+      fifo: PixelFifo::new(),
+    }
+  }
+
+  pub fn lcd_enabled(&self) -> bool {
+    self.lcdc & 0x80 != 0
+  }
+
+  // 160x144 pixels as 0RGB, row by row
+  pub fn framebuffer(&self) -> &[u32] {
+    &self.framebuffer
+  }
+
+  pub fn take_hblanks(&mut self) -> u32 {
+    std::mem::replace(&mut self.hblanks, 0)
+  }
+
+  // 0x8000-0x9FFF through the bank selected in VBK
+  pub fn read_vram(&self, address: u16) -> u8 {
+    self.vram[self.vram_bank * VRAM_SIZE + (address as usize - 0x8000)]
+  }
+
+  pub fn write_vram(&mut self, address: u16, value: u8) {
+    self.vram[self.vram_bank * VRAM_SIZE + (address as usize - 0x8000)] = value;
+  }
+
+  // 0xFF40-0xFF4B, except 0xFF46 which belongs to OAM DMA, and the CGB
+  // VBK and palette registers
+  pub fn read(&self, address: u16) -> u8 {
+    match address {
+      0xFF40 => self.lcdc,
+      0xFF41 => self.read_stat(),
+      0xFF42 => self.scy,
+      0xFF43 => self.scx,
+      0xFF44 => self.ly,
+      0xFF45 => self.lyc,
+      0xFF47 => self.bgp,
+      0xFF48 => self.obp0,
+      0xFF49 => self.obp1,
+      0xFF4A => self.wy,
+      0xFF4B => self.wx,
+      0xFF4F if self.cgb => 0xFE | self.vram_bank as u8,
+      0xFF68 if self.cgb => 0x40 | self.bcps,
+      0xFF69 if self.cgb => self.bg_palettes[(self.bcps & 0x3F) as usize],
+      0xFF6A if self.cgb => 0x40 | self.ocps,
+      0xFF6B if self.cgb => self.obj_palettes[(self.ocps & 0x3F) as usize],
+      _ => 0xFF,
+    }
+  }
+
+  // Returns the interrupts raised by the write
+  pub fn write(&mut self, address: u16, value: u8) -> u8 {
+    match address {
+      0xFF40 => {
+        let was_enabled = self.lcd_enabled();
+        self.lcdc = value;
+
+        if was_enabled && !self.lcd_enabled() {
+          // Switching the LCD off parks it at the start of the frame
+          self.ly = 0;
+          self.dot = 0;
+          self.mode = Mode::HBlank;
+        } else if !was_enabled && self.lcd_enabled() {
+          self.mode = Mode::OamScan;
+        }
+      },
+      0xFF41 => self.stat = value & 0x78,
+      0xFF42 => self.scy = value,
+      0xFF43 => self.scx = value,
+      0xFF45 => self.lyc = value,
+      0xFF47 => self.bgp = value,
+      0xFF48 => self.obp0 = value,
+      0xFF49 => self.obp1 = value,
+      0xFF4A => self.wy = value,
+      0xFF4B => self.wx = value,
+      0xFF4F if self.cgb => self.vram_bank = (value & 0x01) as usize,
+      0xFF68 if self.cgb => self.bcps = value & 0xBF,
+      0xFF69 if self.cgb => {
+        self.bg_palettes[(self.bcps & 0x3F) as usize] = value;
+        self.bcps = increment_palette_index(self.bcps);
+      },
+      0xFF6A if self.cgb => self.ocps = value & 0xBF,
+      0xFF6B if self.cgb => {
+        self.obj_palettes[(self.ocps & 0x3F) as usize] = value;
+        self.ocps = increment_palette_index(self.ocps);
+      },
+      _ => {},
+    }
+
+    self.update_stat_line()
+  }
+
+  fn read_stat(&self) -> u8 {
+    let coincidence = (self.ly == self.lyc) as u8;
+    let mode = if self.lcd_enabled() { self.mode as u8 } else { 0 };
+
+    0x80 | self.stat | (coincidence << 2) | mode
+  }
+
+  // Advances by T-cycles, returning the interrupts to request
+  pub fn tick(&mut self, cycles: u64) -> u8 {
+    let mut interrupts = 0;
+
+    if !self.lcd_enabled() {
+      return interrupts;
+    }
+
+    for _ in 0..cycles {
+      interrupts |= self.step();
+    }
+
+    interrupts
+  }
+
+  fn step(&mut self) -> u8 {
+    let mut interrupts = 0;
+
+    self.dot += 1;
+
+    if self.dot == DOTS_PER_LINE {
+      self.dot = 0;
+      self.ly = (self.ly + 1) % LINES_PER_FRAME;
+    }
+
+    let mode = if self.ly >= VISIBLE_LINES {
+      Mode::VBlank
+    } else if self.dot < OAM_SCAN_DOTS {
+      Mode::OamScan
+    } else if self.renderer == Renderer::Fifo {
+      self.fifo_mode()
+    } else if self.dot < OAM_SCAN_DOTS + PIXEL_TRANSFER_DOTS {
+      Mode::PixelTransfer
+    } else {
+      Mode::HBlank
+    };
+
+    if mode != self.mode {
+      self.mode = mode;
+
+      match mode {
+        Mode::PixelTransfer => {
+          // Once LY has matched WY the window stays armed for the rest of the frame
+          if self.ly == self.wy {
+            self.window_triggered = true;
+          }
+
+          if self.renderer == Renderer::Fifo {
+            self.start_fifo_line();
+          }
+        },
+        Mode::HBlank => {
+          self.hblanks += 1;
+
+          match self.renderer {
+            Renderer::Scanline => self.render_line(),
+            Renderer::Fifo => {
+              if self.fifo.window_active {
+                self.window_line += 1;
+              }
+            },
+          }
+        },
+        Mode::VBlank => {
+          self.frame_ready = true;
+          self.window_line = 0;
+          self.window_triggered = false;
+          interrupts |= VBLANK_INTERRUPT;
+        },
+        Mode::OamScan => {},
+      }
+    }
+
+    interrupts | self.update_stat_line()
+  }
+
+  // Mode 3 lasts until the FIFO has pushed all 160 pixels
+  fn fifo_mode(&mut self) -> Mode {
+    match self.mode {
+      Mode::OamScan => Mode::PixelTransfer,
+      Mode::PixelTransfer => {
+        let mut fifo = std::mem::replace(&mut self.fifo, PixelFifo::new());
+
+        if let Some(pixel) = fifo.step(self) {
+          let colour = self.mix_pixel(pixel.background, pixel.attributes, pixel.sprite);
+          self.framebuffer[self.ly as usize * SCREEN_WIDTH + pixel.x] = colour;
+        }
+
+        let done = fifo.is_done();
+        self.fifo = fifo;
+
+        if done { Mode::HBlank } else { Mode::PixelTransfer }
+      },
+      _ => Mode::HBlank,
+    }
+  }
+
+  fn start_fifo_line(&mut self) {
+    let mut fifo = std::mem::replace(&mut self.fifo, PixelFifo::new());
+    fifo.start_line(self);
+    self.fifo = fifo;
+  }
+
+  // The STAT interrupt fires on the rising edge of the OR of its sources,
+  // so overlapping sources do not retrigger it
+  fn update_stat_line(&mut self) -> u8 {
+    let line = self.lcd_enabled() && (
+      (self.stat & 0x40 != 0 && self.ly == self.lyc)
+        || (self.stat & 0x20 != 0 && self.mode == Mode::OamScan)
+        || (self.stat & 0x10 != 0 && self.mode == Mode::VBlank)
+        || (self.stat & 0x08 != 0 && self.mode == Mode::HBlank)
+    );
+
+    let rising_edge = line && !self.stat_line;
+    self.stat_line = line;
+
+    if rising_edge { STAT_INTERRUPT } else { 0 }
+  }
+
+  fn render_line(&mut self) {
+    self.render_background_line();
+    let sprite_line = self.render_sprite_line();
+
+    let ly = self.ly as usize;
+    for (x, sprite) in sprite_line.iter().enumerate() {
+      let colour = self.mix_pixel(self.bg_line[x], self.bg_attributes[x], *sprite);
+      self.framebuffer[ly * SCREEN_WIDTH + x] = colour;
+    }
+  }
+
+  // Final colour of a pixel from its background colour index and map
+  // attributes and the sprite pixel on top of it, through the current palettes
+  pub fn mix_pixel(&self, background_colour: u8, attributes: u8, sprite: Option<SpritePixel>) -> u32 {
+    if !self.cgb {
+      let shade = match sprite {
+        Some(sprite) if !(sprite.behind_background && background_colour != 0) => {
+          (sprite.palette >> (sprite.colour * 2)) & 0x03
+        },
+        _ => (self.bgp >> (background_colour * 2)) & 0x03,
+      };
+
+      return DMG_SHADES[shade as usize];
+    }
+
+    // With LCDC bit 0 clear sprites always win, otherwise a non-zero
+    // background pixel wins when either the map or the sprite asks for it
+    let sprite_visible = match sprite {
+      Some(sprite) => {
+        self.lcdc & 0x01 == 0
+          || background_colour == 0
+          || (attributes & 0x80 == 0 && !sprite.behind_background)
+      },
+      None => false,
+    };
+
+    match sprite {
+      Some(sprite) if sprite_visible => cgb_colour(&self.obj_palettes, sprite.palette, sprite.colour),
+      _ => cgb_colour(&self.bg_palettes, attributes & 0x07, background_colour),
+    }
+  }
+
+  fn sprite_height(&self) -> i16 {
+    if self.lcdc & 0x04 != 0 { 16 } else { 8 }
+  }
+
+  // The first ten sprites in OAM order that overlap the current line
+  pub fn select_sprites(&self) -> Vec<Sprite> {
+    let height = self.sprite_height();
+    let ly = self.ly as i16;
+
+    self.oam.chunks(4)
+      .enumerate()
+      .map(|(index, entry)| Sprite {
+        y: entry[0] as i16 - 16,
+        x: entry[1] as i16 - 8,
+        tile: entry[2],
+        attributes: entry[3],
+        index,
+      })
+      .filter(|sprite| ly >= sprite.y && ly < sprite.y + height)
+      .take(MAX_SPRITES_PER_LINE)
+      .collect()
+  }
+
+  // Colour index of a sprite's pixel at column x of the sprite (0-7) on the
+  // current line, honouring flips and 8x16 tiles
+  pub fn sprite_pixel(&self, sprite: &Sprite, x: u8) -> u8 {
+    let height = self.sprite_height();
+    let mut row = self.ly as i16 - sprite.y;
+
+    if sprite.attributes & 0x40 != 0 {
+      row = height - 1 - row;
+    }
+
+    let tile = if height == 16 { sprite.tile & 0xFE } else { sprite.tile };
+    let x = if sprite.attributes & 0x20 != 0 { 7 - x } else { x };
+    let bank = if self.cgb { (sprite.attributes >> 3) as usize & 0x01 } else { 0 };
+
+    let offset = bank * VRAM_SIZE + tile as usize * 16 + (row as usize / 8) * 16;
+    self.tile_pixel(offset, x, row as u8 % 8)
+  }
+
+  // On DMG the sprite with the lowest X wins a pixel, ties going to the
+  // lowest OAM index, even if it ends up hidden behind the background. CGB
+  // only looks at the OAM index.
+  fn render_sprite_line(&self) -> [Option<SpritePixel>; SCREEN_WIDTH] {
+    let mut line = [None; SCREEN_WIDTH];
+
+    if self.lcdc & 0x02 == 0 {
+      return line;
+    }
+
+    let mut sprites = self.select_sprites();
+    if !self.cgb {
+      sprites.sort_by_key(|sprite| (sprite.x, sprite.index));
+    }
+
+    for sprite in sprites.iter() {
+      for column in 0..8 {
+        let x = sprite.x + column as i16;
+
+        if x < 0 || x >= SCREEN_WIDTH as i16 || line[x as usize].is_some() {
+          continue;
+        }
+
+        line[x as usize] = self.sprite_fifo_pixel(sprite, column);
+      }
+    }
+
+    line
+  }
+
+  // A sprite's pixel at column x with its palette and priority, None when
+  // transparent
+  pub fn sprite_fifo_pixel(&self, sprite: &Sprite, x: u8) -> Option<SpritePixel> {
+    let colour = self.sprite_pixel(sprite, x);
+    if colour == 0 {
+      return None;
+    }
+
+    let palette = if self.cgb {
+      sprite.attributes & 0x07
+    } else if sprite.attributes & 0x10 != 0 {
+      self.obp1
+    } else {
+      self.obp0
+    };
+
+    Some(SpritePixel {
+      colour,
+      palette,
+      behind_background: sprite.attributes & 0x80 != 0,
+      index: sprite.index,
+    })
+  }
+
+  fn render_background_line(&mut self) {
+    // On DMG, LCDC bit 0 blanks both the background and the window
+    if !self.cgb && self.lcdc & 0x01 == 0 {
+      self.bg_line = [0; SCREEN_WIDTH];
+      self.bg_attributes = [0; SCREEN_WIDTH];
+      return;
+    }
+
+    let background_map = if self.lcdc & 0x08 != 0 { 0x1C00 } else { 0x1800 };
+    let y = self.ly.wrapping_add(self.scy);
+
+    for x in 0..SCREEN_WIDTH {
+      let background_x = (x as u8).wrapping_add(self.scx);
+      let (colour, attributes) = self.tile_map_pixel(background_map, background_x, y);
+      self.bg_line[x] = colour;
+      self.bg_attributes[x] = attributes;
+    }
+
+    let window_x = self.wx as i16 - 7;
+    let window_visible = self.lcdc & 0x20 != 0 && self.window_triggered && window_x < SCREEN_WIDTH as i16;
+
+    if window_visible {
+      let window_map = if self.lcdc & 0x40 != 0 { 0x1C00 } else { 0x1800 };
+
+      for x in window_x.max(0) as usize..SCREEN_WIDTH {
+        let column = (x as i16 - window_x) as u8;
+        let (colour, attributes) = self.tile_map_pixel(window_map, column, self.window_line);
+        self.bg_line[x] = colour;
+        self.bg_attributes[x] = attributes;
+      }
+
+      self.window_line += 1;
+    }
+  }
+
+  // Colour index and attributes of pixel (x, y) of the 256x256 map at
+  // map_offset in VRAM
+  fn tile_map_pixel(&self, map_offset: usize, x: u8, y: u8) -> (u8, u8) {
+    let entry = map_offset + (y as usize / 8) * 32 + x as usize / 8;
+    let attributes = self.map_attributes(entry);
+    let x = if attributes & 0x20 != 0 { 7 - x % 8 } else { x % 8 };
+
+    // The row offset already points at line y of the tile
+    let colour = self.tile_pixel(self.bg_tile_row_offset(self.vram[entry], attributes, y % 8), x, 0);
+    (colour, attributes)
+  }
+
+  // CGB keeps the attributes of a map entry at the same place in bank 1:
+  // bits 0-2 palette, bit 3 tile bank, bit 5 X flip, bit 6 Y flip, bit 7
+  // priority over sprites
+  pub fn map_attributes(&self, entry: usize) -> u8 {
+    if self.cgb { self.vram[VRAM_SIZE + entry] } else { 0 }
+  }
+
+  // VRAM offset of row `row` of a background tile, honouring the bank and
+  // Y flip attributes
+  pub fn bg_tile_row_offset(&self, tile_index: u8, attributes: u8, row: u8) -> usize {
+    let row = if attributes & 0x40 != 0 { 7 - row } else { row };
+    let bank = (attributes >> 3) as usize & 0x01;
+
+    bank * VRAM_SIZE + self.tile_data_offset(tile_index) + row as usize * 2
+  }
+
+  // LCDC bit 4 picks 0x8000 with unsigned indices or 0x8800 with signed ones
+  pub fn tile_data_offset(&self, tile_index: u8) -> usize {
+    if self.lcdc & 0x10 != 0 {
+      tile_index as usize * 16
+    } else {
+      (0x1000 + (tile_index as i8 as i32) * 16) as usize
+    }
+  }
+
+  fn tile_pixel(&self, tile_offset: usize, x: u8, y: u8) -> u8 {
+    let low = self.vram[tile_offset + y as usize * 2];
+    let high = self.vram[tile_offset + y as usize * 2 + 1];
+    let bit = 7 - x;
+
+    (((high >> bit) & 0x01) << 1) | ((low >> bit) & 0x01)
+  }
+}
+
+// BCPS and OCPS bit 7 advances the index after each data write, wrapping
+// within the 64 bytes
+fn increment_palette_index(selector: u8) -> u8 {
+  if selector & 0x80 == 0 {
+    return selector;
+  }
+
+  0x80 | ((selector + 1) & 0x3F)
+}
+
+// RGB555, little-endian, scaled to 0RGB
+fn cgb_colour(palettes: &[u8; PALETTE_RAM_SIZE], palette: u8, colour: u8) -> u32 {
+  let offset = (palette as usize * 4 + colour as usize) * 2;
+  let rgb = palettes[offset] as u32 | ((palettes[offset + 1] as u32) << 8);
+
+  let scale = |component: u32| (component << 3) | (component >> 2);
+  let red = scale(rgb & 0x1F);
+  let green = scale((rgb >> 5) & 0x1F);
+  let blue = scale((rgb >> 10) & 0x1F);
+
+  (red << 16) | (green << 8) | blue
+}
@@ -0,0 +1,82 @@
+use minifb::Key;
+use minifb::Scale;
+use minifb::Window;
+use minifb::WindowOptions;
+
+use joypad::joypad::Button;
+use ppu::ppu::SCREEN_HEIGHT;
+use ppu::ppu::SCREEN_WIDTH;
+
+// Arrows for the D-pad, X and Z for A and B, Enter for Start and Backspace
+// for Select
+const KEY_MAP: [(Key, Button); 8] = [
+  (Key::Right, Button::Right),
+  (Key::Left, Button::Left),
+  (Key::Up, Button::Up),
+  (Key::Down, Button::Down),
+  (Key::X, Button::A),
+  (Key::Z, Button::B),
+  (Key::Backspace, Button::Select),
+  (Key::Enter, Button::Start),
+];
+
+// IJKL tilt the console for cartridges with an accelerometer, by 1 g
+// while held
+const TILT_LEFT: Key = Key::J;
+const TILT_RIGHT: Key = Key::L;
+const TILT_UP: Key = Key::I;
+const TILT_DOWN: Key = Key::K;
+
+pub struct Screen {
+  window: Window,
+}
+
+impl Screen {
+  // None when no display is available, e.g. on CI
+  pub fn new(title: &str) -> Option<Self> {
+    let options = WindowOptions {
+      scale: Scale::X4,
+      ..WindowOptions::default()
+    };
+
+    match Window::new(title, SCREEN_WIDTH, SCREEN_HEIGHT, options) {
+      Ok(window) => Some(Self { window }),
+      Err(e) => {
+        println!("Could not open a window, running headless: {}", e);
+        None
+      },
+    }
+  }
+
+  pub fn is_open(&self) -> bool {
+    self.window.is_open()
+  }
+
+  pub fn update(&mut self, framebuffer: &[u32]) {
+    if let Err(e) = self.window.update_with_buffer(framebuffer, SCREEN_WIDTH, SCREEN_HEIGHT) {
+      println!("Error updating window: {}", e);
+    }
+  }
+
+  // Processes window events without redrawing, for when no frames come in
+  pub fn poll(&mut self) {
+    self.window.update();
+  }
+
+  // Every button with whether its key is held
+  pub fn buttons(&self) -> Vec<(Button, bool)> {
+    KEY_MAP.iter()
+      .map(|&(key, button)| (button, self.window.is_key_down(key)))
+      .collect()
+  }
+
+  // Tilt in g from the held keys, x towards the right and y towards the
+  // bottom
+  pub fn tilt(&self) -> (f32, f32) {
+    let axis = |negative, positive| {
+      self.window.is_key_down(positive) as i32 as f32 - self.window.is_key_down(negative) as i32 as f32
+    };
+
+    (axis(TILT_LEFT, TILT_RIGHT), axis(TILT_UP, TILT_DOWN))
+  }
+}
@@ -0,0 +1,45 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use serial::serial::SerialDevice;
+
+// One end of the cable as seen from the other
+#[derive(Copy, Clone)]
+struct Line {
+  out: bool, // The bit this end shifts out next
+  pending: Option<bool>, // A bit clocked over to this end, not shifted in yet
+}
+
+// One end of a link cable between two Game Boys run side by side. The one
+// on the internal clock pulses the cable, and the other picks the bit up
+// the next time it is ticked, so both need ticking in steps well under a
+// bit's 512 cycles.
+pub struct LinkPort {
+  end: usize,
+  lines: Rc<RefCell<[Line; 2]>>,
+}
+
+// The two ends of a new cable
+pub fn link_cable() -> (LinkPort, LinkPort) {
+  let lines = Rc::new(RefCell::new([Line { out: true, pending: None }; 2]));
+
+  (LinkPort { end: 0, lines: lines.clone() }, LinkPort { end: 1, lines })
+}
+
+impl SerialDevice for LinkPort {
+  fn exchange_bit(&mut self, bit: bool) -> bool {
+    let mut lines = self.lines.borrow_mut();
+    let other = 1 - self.end;
+
+    lines[self.end].out = bit;
+    lines[other].pending = Some(bit);
+    lines[other].out
+  }
+
+  fn external_bit(&mut self, bit: bool) -> Option<bool> {
+    let mut lines = self.lines.borrow_mut();
+
+    lines[self.end].out = bit;
+    lines[self.end].pending.take()
+  }
+}
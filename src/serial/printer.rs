@@ -0,0 +1,256 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Error;
+use std::io::Result;
+use std::path::Path;
+use std::path::PathBuf;
+
+use png;
+use serial::serial::SerialDevice;
+
+// Packets start with two magic bytes, then command, compression flag,
+// data length, data and a checksum over everything after the magic. The
+// printer answers the two bytes after that with 0x81 and its status.
+const MAGIC: [u8; 2] = [0x88, 0x33];
+const ALIVE: u8 = 0x81;
+
+const INITIALIZE: u8 = 0x01;
+const PRINT: u8 = 0x02;
+const DATA: u8 = 0x04;
+
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_UNPRINTED_DATA: u8 = 0x08;
+const STATUS_FULL: u8 = 0x04;
+
+// Image data holds up to 9 packets of two tile rows, 20 tiles wide
+const BUFFER_SIZE: usize = 0x2000;
+const WIDTH: usize = 160;
+const TILE_SIZE: usize = 16;
+const TILES_PER_ROW: usize = WIDTH / 8;
+
+// Printed shades, from white to black
+const SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+  Magic(usize),
+  Command,
+  Compression,
+  LengthLow,
+  LengthHigh,
+  Data,
+  ChecksumLow,
+  ChecksumHigh,
+  Alive,
+  Status,
+}
+
+// The Game Boy Printer. Each print is saved as print-<n>.png in a
+// directory.
+pub struct GameBoyPrinter {
+  directory: PathBuf,
+  prints: usize,
+  state: State,
+  // The byte coming in, the bits of it so far and the byte going out
+  byte: u8,
+  bits: u8,
+  response: u8,
+  command: u8,
+  compressed: bool,
+  length: usize,
+  data: Vec<u8>,
+  checksum: u16,
+  received_checksum: u16,
+  image: Vec<u8>, // Tile data waiting to be printed
+  status: u8,
+}
+
+impl GameBoyPrinter {
+  pub fn new(directory: &Path) -> Self {
+    Self {
+      directory: directory.to_path_buf(),
+      prints: 0,
+      state: State::Magic(0),
+      byte: 0,
+      bits: 0,
+      response: 0,
+      command: 0,
+      compressed: false,
+      length: 0,
+      data: Vec::new(),
+      checksum: 0,
+      received_checksum: 0,
+      image: Vec::new(),
+      status: 0,
+    }
+  }
+
+  fn receive(&mut self, byte: u8) {
+    if let State::Compression | State::LengthLow | State::LengthHigh | State::Data = self.state {
+      self.checksum = self.checksum.wrapping_add(byte as u16);
+    }
+
+    self.response = 0x00;
+    self.state = match self.state {
+      State::Magic(i) if byte == MAGIC[i] => {
+        if i + 1 == MAGIC.len() { State::Command } else { State::Magic(i + 1) }
+      },
+      State::Magic(_) => State::Magic(if byte == MAGIC[0] { 1 } else { 0 }),
+      State::Command => {
+        self.command = byte;
+        self.checksum = byte as u16;
+        State::Compression
+      },
+      State::Compression => {
+        self.compressed = byte & 0x01 != 0;
+        State::LengthLow
+      },
+      State::LengthLow => {
+        self.length = byte as usize;
+        State::LengthHigh
+      },
+      State::LengthHigh => {
+        self.length |= (byte as usize) << 8;
+        self.data.clear();
+        if self.length == 0 { State::ChecksumLow } else { State::Data }
+      },
+      State::Data => {
+        self.data.push(byte);
+        if self.data.len() == self.length { State::ChecksumLow } else { State::Data }
+      },
+      State::ChecksumLow => {
+        self.received_checksum = byte as u16;
+        State::ChecksumHigh
+      },
+      State::ChecksumHigh => {
+        self.received_checksum |= (byte as u16) << 8;
+        self.response = ALIVE;
+        State::Alive
+      },
+      State::Alive => {
+        self.run_command();
+        self.response = self.status;
+        State::Status
+      },
+      State::Status => State::Magic(0),
+    };
+  }
+
+  fn run_command(&mut self) {
+    if self.received_checksum != self.checksum {
+      self.status |= STATUS_CHECKSUM_ERROR;
+      return;
+    }
+    self.status &= !STATUS_CHECKSUM_ERROR;
+
+    match self.command {
+      INITIALIZE => {
+        self.image.clear();
+        self.status = 0;
+      },
+      DATA => {
+        let data = std::mem::take(&mut self.data);
+        let data = if self.compressed { decompress(&data) } else { data };
+        let space = BUFFER_SIZE - self.image.len();
+        self.image.extend_from_slice(&data[..data.len().min(space)]);
+
+        if !self.image.is_empty() {
+          self.status |= STATUS_UNPRINTED_DATA;
+        }
+        if self.image.len() == BUFFER_SIZE {
+          self.status |= STATUS_FULL;
+        }
+      },
+      PRINT if self.data.len() >= 4 => {
+        let palette = self.data[2];
+        if let Err(e) = self.print(palette) {
+          println!("Error saving printout: {}", e);
+        }
+
+        self.image.clear();
+        self.status &= !(STATUS_UNPRINTED_DATA | STATUS_FULL);
+      },
+      _ => {},
+    }
+  }
+
+  // Saves the image data as greyscale, the palette mapping colour numbers
+  // to shades two bits each. A palette of 0 means the default one.
+  fn print(&mut self, palette: u8) -> Result<()> {
+    let palette = if palette == 0 { 0xE4 } else { palette };
+    let rows = self.image.len() / (TILES_PER_ROW * TILE_SIZE);
+    let height = rows * 8;
+    let mut pixels = vec![0; WIDTH * height];
+
+    for (tile, data) in self.image.chunks_exact(TILE_SIZE).enumerate() {
+      let (tile_x, tile_y) = (tile % TILES_PER_ROW, tile / TILES_PER_ROW);
+      if tile_y >= rows {
+        break;
+      }
+
+      for y in 0..8 {
+        let (low, high) = (data[y * 2], data[y * 2 + 1]);
+
+        for x in 0..8 {
+          let colour = ((low >> (7 - x)) & 0x01) | (((high >> (7 - x)) & 0x01) << 1);
+          let shade = (palette >> (colour * 2)) & 0x03;
+          pixels[(tile_y * 8 + y) * WIDTH + tile_x * 8 + x] = SHADES[shade as usize];
+        }
+      }
+    }
+
+    self.prints += 1;
+    let path = self.directory.join(format!("print-{}.png", self.prints));
+
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), WIDTH as u32, height as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()
+      .map_err(Error::other)?;
+    writer.write_image_data(&pixels)
+      .map_err(Error::other)
+  }
+}
+
+impl SerialDevice for GameBoyPrinter {
+  fn exchange_bit(&mut self, bit: bool) -> bool {
+    let out = self.response & 0x80 != 0;
+    self.response <<= 1;
+
+    self.byte = (self.byte << 1) | bit as u8;
+    self.bits += 1;
+
+    if self.bits == 8 {
+      self.bits = 0;
+      self.receive(self.byte);
+    }
+
+    out
+  }
+}
+
+// Run-length encoding: a byte with bit 7 set repeats the next byte
+// (n & 0x7F) + 2 times, otherwise the next n + 1 bytes are copied as-is
+fn decompress(data: &[u8]) -> Vec<u8> {
+  let mut output = Vec::new();
+  let mut i = 0;
+
+  while i < data.len() {
+    let n = data[i] as usize;
+    i += 1;
+
+    if n & 0x80 != 0 {
+      if let Some(&value) = data.get(i) {
+        output.extend(std::iter::repeat_n(value, (n & 0x7F) + 2));
+      }
+      i += 1;
+    } else {
+      let end = (i + n + 1).min(data.len());
+      output.extend_from_slice(&data[i..end]);
+      i = end;
+    }
+  }
+
+  output
+}
@@ -0,0 +1,160 @@
+// SB and SC at 0xFF01-0xFF02. A transfer shifts SB out MSB first while the
+// bits from the other end shift in, one per clock pulse. On the internal
+// clock the Game Boy drives the pulses, on the external clock it waits for
+// the other end to. Whatever is on the other end implements SerialDevice.
+
+use std::io::Write;
+
+pub const SERIAL_INTERRUPT: u8 = 0x08;
+
+// CPU cycles per bit on the internal clock: 8192 Hz, or 262144 Hz with the
+// CGB fast clock. Both double along with the CPU in double-speed mode.
+const BIT_CYCLES: u64 = 512;
+const FAST_BIT_CYCLES: u64 = 16;
+
+pub trait SerialDevice {
+  // A clock pulse from the Game Boy: takes the bit it shifts out and
+  // returns the bit shifted in
+  fn exchange_bit(&mut self, bit: bool) -> bool;
+
+  // Polled while the Game Boy waits on the external clock, with the bit it
+  // would shift out. Returns the bit shifted in when the device pulsed the
+  // clock.
+  fn external_bit(&mut self, _bit: bool) -> Option<bool> {
+    None
+  }
+}
+
+// No cable: the line is pulled high, so every bit reads 1, and there is
+// never an external clock
+pub struct Disconnected;
+
+impl SerialDevice for Disconnected {
+  fn exchange_bit(&mut self, _bit: bool) -> bool {
+    true
+  }
+}
+
+// Writes every byte sent out, such as the results test ROMs print, and
+// answers like nothing is connected
+pub struct SerialLogger<W: Write> {
+  output: W,
+  byte: u8,
+  bits: u8,
+}
+
+impl<W: Write> SerialLogger<W> {
+  pub fn new(output: W) -> Self {
+    Self { output, byte: 0, bits: 0 }
+  }
+}
+
+impl<W: Write> SerialDevice for SerialLogger<W> {
+  fn exchange_bit(&mut self, bit: bool) -> bool {
+    self.byte = (self.byte << 1) | bit as u8;
+    self.bits += 1;
+
+    if self.bits == 8 {
+      self.bits = 0;
+      // The log is best effort, the Game Boy doesn't notice it failing
+      let _ = self.output.write_all(&[self.byte]).and_then(|_| self.output.flush());
+    }
+
+    true
+  }
+}
+
+pub struct Serial {
+  pub data: u8, // SB
+  control: u8, // SC bits 7 (transfer), 1 (CGB fast clock) and 0 (internal clock)
+  bits_left: u8,
+  cycles: u64, // Since the last bit on the internal clock
+  pub cgb: bool,
+  device: Box<dyn SerialDevice>,
+}
+
+impl Serial {
+  pub fn new() -> Self {
+    Self {
+      data: 0,
+      control: 0,
+      bits_left: 0,
+      cycles: 0,
+      cgb: false,
+      device: Box::new(Disconnected),
+    }
+  }
+
+  pub fn connect(&mut self, device: Box<dyn SerialDevice>) {
+    self.device = device;
+  }
+
+  pub fn read(&self, address: u16) -> u8 {
+    match address {
+      0xFF01 => self.data,
+      0xFF02 if self.cgb => 0x7C | self.control,
+      0xFF02 => 0x7E | self.control,
+      _ => 0xFF,
+    }
+  }
+
+  pub fn write(&mut self, address: u16, value: u8) {
+    match address {
+      0xFF01 => self.data = value,
+      0xFF02 => {
+        self.control = value & if self.cgb { 0x83 } else { 0x81 };
+
+        if self.transferring() {
+          self.bits_left = 8;
+          self.cycles = 0;
+        }
+      },
+      _ => {},
+    }
+  }
+
+  fn transferring(&self) -> bool {
+    self.control & 0x80 != 0
+  }
+
+  fn bit_cycles(&self) -> u64 {
+    if self.control & 0x02 != 0 { FAST_BIT_CYCLES } else { BIT_CYCLES }
+  }
+
+  // Advances by CPU cycles, returning the interrupt raised when a transfer
+  // completes
+  pub fn tick(&mut self, cycles: u64) -> u8 {
+    if !self.transferring() {
+      return 0;
+    }
+
+    if self.control & 0x01 != 0 {
+      self.cycles += cycles;
+
+      while self.transferring() && self.cycles >= self.bit_cycles() {
+        self.cycles -= self.bit_cycles();
+
+        let bit = self.device.exchange_bit(self.data & 0x80 != 0);
+        self.shift_in(bit);
+      }
+    } else {
+      while self.transferring() {
+        match self.device.external_bit(self.data & 0x80 != 0) {
+          Some(bit) => self.shift_in(bit),
+          None => break,
+        }
+      }
+    }
+
+    if self.transferring() { 0 } else { SERIAL_INTERRUPT }
+  }
+
+  fn shift_in(&mut self, bit: bool) {
+    self.data = (self.data << 1) | bit as u8;
+    self.bits_left -= 1;
+
+    if self.bits_left == 0 {
+      self.control &= 0x7F;
+    }
+  }
+}
@@ -0,0 +1,739 @@
+use apu::blip::BlipBuffer;
+use apu::filter::HighPass;
+use apu::midi::MidiLog;
+use memory::memory::Memory;
+
+// T-cycles between frame sequencer steps
+const FRAME_STEP: u64 = 8192;
+
+struct Setup {
+  memory: Memory
+}
+
+impl Setup {
+  pub fn new() -> Self {
+    Self {
+      memory: Memory::new(),
+    }
+  }
+}
+
+#[test]
+fn test_square_trigger_and_dac() {
+  let mut setup = Setup::new();
+
+  // DAC off: triggering does not start the channel
+  setup.memory.write(0xFF17, 0x00);
+  setup.memory.write(0xFF19, 0x80);
+  assert!(!setup.memory.apu.square2.enabled);
+
+  setup.memory.write(0xFF17, 0xF0);
+  setup.memory.write(0xFF19, 0x80);
+  assert!(setup.memory.apu.square2.enabled);
+
+  // Switching the DAC off stops it
+  setup.memory.write(0xFF17, 0x07);
+  assert!(!setup.memory.apu.square2.enabled);
+}
+
+#[test]
+fn test_square_register_read_masks() {
+  let mut setup = Setup::new();
+
+  setup.memory.write(0xFF10, 0x00);
+  setup.memory.write(0xFF11, 0x80);
+  setup.memory.write(0xFF13, 0x12);
+  setup.memory.write(0xFF14, 0x40);
+
+  assert_eq!(setup.memory.read(0xFF10), 0x80);
+  assert_eq!(setup.memory.read(0xFF11), 0xBF);
+  assert_eq!(setup.memory.read(0xFF13), 0xFF);
+  assert_eq!(setup.memory.read(0xFF14), 0xFF);
+  assert_eq!(setup.memory.read(0xFF15), 0xFF);
+}
+
+#[test]
+fn test_square_duty_waveform() {
+  let mut setup = Setup::new();
+
+  // 50% duty at frequency 2047, 4 T-cycles per duty step, full volume
+  setup.memory.write(0xFF16, 0x80);
+  setup.memory.write(0xFF17, 0xF0);
+  setup.memory.write(0xFF18, 0xFF);
+  setup.memory.write(0xFF19, 0x87);
+
+  let mut wave = Vec::new();
+  for _ in 0..8 {
+    setup.memory.apu.tick(4);
+    wave.push(setup.memory.apu.square2.output());
+  }
+
+  assert_eq!(wave, vec![0, 0, 0, 0, 15, 15, 15, 15]);
+}
+
+#[test]
+fn test_square_length_counter() {
+  let mut setup = Setup::new();
+
+  // Length 62 leaves 2 clocks, at 256 Hz
+  setup.memory.write(0xFF17, 0xF0);
+  setup.memory.write(0xFF16, 0x3E);
+  setup.memory.write(0xFF19, 0xC0);
+
+  setup.memory.tick(FRAME_STEP);
+  assert!(setup.memory.apu.square2.enabled);
+  setup.memory.tick(FRAME_STEP * 2);
+  assert!(!setup.memory.apu.square2.enabled);
+
+  // Without length enabled it keeps playing
+  setup.memory.write(0xFF19, 0x80);
+  setup.memory.tick(FRAME_STEP * 256);
+  assert!(setup.memory.apu.square2.enabled);
+}
+
+#[test]
+fn test_length_enable_extra_clock() {
+  let mut setup = Setup::new();
+
+  setup.memory.write(0xFF17, 0xF0);
+  setup.memory.write(0xFF16, 0x3F);
+  setup.memory.write(0xFF19, 0x80);
+
+  // Step 0 ran, so the next one does not clock length: enabling it now
+  // takes the last clock
+  setup.memory.tick(FRAME_STEP);
+  setup.memory.write(0xFF19, 0x40);
+  assert!(!setup.memory.apu.square2.enabled);
+}
+
+#[test]
+fn test_square_envelope() {
+  let mut setup = Setup::new();
+
+  // Volume 2, decreasing every 64 Hz tick
+  setup.memory.write(0xFF17, 0x21);
+  setup.memory.write(0xFF19, 0x80);
+  assert_eq!(setup.memory.apu.square2.envelope.volume, 2);
+
+  setup.memory.tick(FRAME_STEP * 8);
+  assert_eq!(setup.memory.apu.square2.envelope.volume, 1);
+  setup.memory.tick(FRAME_STEP * 16);
+  assert_eq!(setup.memory.apu.square2.envelope.volume, 0);
+
+  // Increasing stops at 15
+  setup.memory.write(0xFF17, 0xE9);
+  setup.memory.write(0xFF19, 0x80);
+  setup.memory.tick(FRAME_STEP * 8 * 4);
+  assert_eq!(setup.memory.apu.square2.envelope.volume, 15);
+}
+
+#[test]
+fn test_sweep_updates_frequency() {
+  let mut setup = Setup::new();
+
+  // Period 1, shift 1, upwards from 0x100
+  setup.memory.write(0xFF10, 0x11);
+  setup.memory.write(0xFF12, 0xF0);
+  setup.memory.write(0xFF13, 0x00);
+  setup.memory.write(0xFF14, 0x81);
+
+  // Sweep runs on steps 2 and 6
+  setup.memory.tick(FRAME_STEP * 3);
+  assert_eq!(setup.memory.apu.square1.frequency, 0x180);
+  setup.memory.tick(FRAME_STEP * 4);
+  assert_eq!(setup.memory.apu.square1.frequency, 0x240);
+}
+
+#[test]
+fn test_sweep_overflow_disables() {
+  let mut setup = Setup::new();
+
+  // The trigger-time check already overflows
+  setup.memory.write(0xFF10, 0x11);
+  setup.memory.write(0xFF12, 0xF0);
+  setup.memory.write(0xFF13, 0xFF);
+  setup.memory.write(0xFF14, 0x87);
+  assert!(!setup.memory.apu.square1.enabled);
+
+  // 0x500 + 0x280 = 0x780 fits, the follow-up check 0x780 + 0x3C0 does not
+  setup.memory.write(0xFF13, 0x00);
+  setup.memory.write(0xFF14, 0x85);
+  assert!(setup.memory.apu.square1.enabled);
+  setup.memory.tick(FRAME_STEP * 3);
+  assert!(!setup.memory.apu.square1.enabled);
+}
+
+#[test]
+fn test_sweep_negate_cleared_after_use() {
+  let mut setup = Setup::new();
+
+  setup.memory.write(0xFF10, 0x19);
+  setup.memory.write(0xFF12, 0xF0);
+  setup.memory.write(0xFF13, 0x00);
+  setup.memory.write(0xFF14, 0x84);
+  assert!(setup.memory.apu.square1.enabled);
+
+  setup.memory.write(0xFF10, 0x11);
+  assert!(!setup.memory.apu.square1.enabled);
+}
+
+fn load_wave_ram(memory: &mut Memory) {
+  for i in 0..16 {
+    let sample = (i as u8 * 2) & 0x0F;
+    memory.write(0xFF30 + i, (sample << 4) | (sample + 1));
+  }
+}
+
+// Starts channel 3 at full volume with the given 11-bit frequency
+fn play_wave(memory: &mut Memory, frequency: u16) {
+  memory.write(0xFF1A, 0x80);
+  memory.write(0xFF1C, 0x20);
+  memory.write(0xFF1D, frequency as u8);
+  memory.write(0xFF1E, 0x80 | (frequency >> 8) as u8);
+}
+
+#[test]
+fn test_wave_playback_and_volume() {
+  let mut setup = Setup::new();
+  load_wave_ram(&mut setup.memory);
+  play_wave(&mut setup.memory, 0x7FF);
+
+  // The first fetch comes 6 T-cycles late and skips sample 0
+  setup.memory.apu.tick(6);
+  assert_eq!(setup.memory.apu.wave.output(), 0);
+
+  let mut samples = Vec::new();
+  for _ in 0..4 {
+    setup.memory.apu.tick(2);
+    samples.push(setup.memory.apu.wave.output());
+  }
+  assert_eq!(samples, vec![1, 2, 3, 4]);
+
+  // 50% and 25% volume shift the sample right, 0 mutes it
+  setup.memory.write(0xFF1C, 0x40);
+  assert_eq!(setup.memory.apu.wave.output(), 2);
+  setup.memory.write(0xFF1C, 0x60);
+  assert_eq!(setup.memory.apu.wave.output(), 1);
+  setup.memory.write(0xFF1C, 0x00);
+  assert_eq!(setup.memory.apu.wave.output(), 0);
+  assert_eq!(setup.memory.read(0xFF1C), 0x9F);
+}
+
+#[test]
+fn test_wave_dac_and_length() {
+  let mut setup = Setup::new();
+
+  play_wave(&mut setup.memory, 0x000);
+  assert!(setup.memory.apu.wave.enabled);
+  setup.memory.write(0xFF1A, 0x00);
+  assert!(!setup.memory.apu.wave.enabled);
+  assert_eq!(setup.memory.read(0xFF1A), 0x7F);
+
+  // 8-bit length, 256 - 0xFE leaves 2 clocks
+  play_wave(&mut setup.memory, 0x000);
+  setup.memory.write(0xFF1B, 0xFE);
+  setup.memory.write(0xFF1E, 0x40);
+  setup.memory.tick(FRAME_STEP * 2);
+  assert!(setup.memory.apu.wave.enabled);
+  setup.memory.tick(FRAME_STEP * 2);
+  assert!(!setup.memory.apu.wave.enabled);
+}
+
+#[test]
+fn test_wave_ram_access_while_playing() {
+  let mut setup = Setup::new();
+  load_wave_ram(&mut setup.memory);
+
+  // 512 T-cycles per sample
+  play_wave(&mut setup.memory, 0x700);
+  setup.memory.apu.tick(512 + 6);
+
+  // On the fetch cycle any address hits the byte being played
+  assert_eq!(setup.memory.read(0xFF3A), 0x01);
+
+  // Any other time DMG reads 0xFF and drops writes
+  setup.memory.apu.tick(4);
+  assert_eq!(setup.memory.read(0xFF3A), 0xFF);
+  setup.memory.write(0xFF3A, 0x99);
+  setup.memory.write(0xFF1A, 0x00);
+  assert_eq!(setup.memory.read(0xFF3A), 0x45);
+  assert_eq!(setup.memory.read(0xFF30), 0x01);
+}
+
+#[test]
+fn test_cgb_wave_ram_access_while_playing() {
+  let mut setup = Setup::new();
+  setup.memory.apu.cgb = true;
+  load_wave_ram(&mut setup.memory);
+
+  play_wave(&mut setup.memory, 0x700);
+  setup.memory.apu.tick(512 * 3 + 6 + 4);
+
+  assert_eq!(setup.memory.read(0xFF30), 0x23);
+  setup.memory.write(0xFF3F, 0x99);
+  setup.memory.write(0xFF1A, 0x00);
+  assert_eq!(setup.memory.read(0xFF31), 0x99);
+}
+
+#[test]
+fn test_wave_retrigger_corrupts_ram_on_dmg() {
+  let mut setup = Setup::new();
+  load_wave_ram(&mut setup.memory);
+
+  // Retrigger just before the fetch of sample 8, in byte 4
+  play_wave(&mut setup.memory, 0x700);
+  setup.memory.apu.tick(512 * 8 + 6 - 2);
+  setup.memory.write(0xFF1E, 0x87);
+  setup.memory.write(0xFF1A, 0x00);
+
+  assert_eq!(setup.memory.read(0xFF30), 0x89);
+  assert_eq!(setup.memory.read(0xFF33), 0xEF);
+  assert_eq!(setup.memory.read(0xFF34), 0x89);
+
+  // Within the first four bytes only byte 0 is overwritten
+  let mut setup = Setup::new();
+  load_wave_ram(&mut setup.memory);
+  play_wave(&mut setup.memory, 0x700);
+  setup.memory.apu.tick(512 * 3 + 6 - 2);
+  setup.memory.write(0xFF1E, 0x87);
+  setup.memory.write(0xFF1A, 0x00);
+
+  assert_eq!(setup.memory.read(0xFF30), 0x23);
+  assert_eq!(setup.memory.read(0xFF31), 0x23);
+  assert_eq!(setup.memory.read(0xFF32), 0x45);
+}
+
+// Starts channel 4 at full volume with the given NR43 value
+fn play_noise(memory: &mut Memory, polynomial: u8) {
+  memory.write(0xFF21, 0xF0);
+  memory.write(0xFF22, polynomial);
+  memory.write(0xFF23, 0x80);
+}
+
+// Bit 0 of the LFSR over a number of clocks, 8 T-cycles apart
+fn noise_bits(memory: &mut Memory, clocks: usize) -> Vec<u16> {
+  (0..clocks).map(|_| {
+    memory.apu.tick(8);
+    memory.apu.noise.lfsr & 0x01
+  }).collect()
+}
+
+#[test]
+fn test_noise_lfsr_sequence() {
+  let mut setup = Setup::new();
+  play_noise(&mut setup.memory, 0x00);
+  assert!(setup.memory.apu.noise.enabled);
+  assert_eq!(setup.memory.apu.noise.lfsr, 0x7FFF);
+
+  // The ones shift out before the first feedback bit reaches bit 0
+  setup.memory.apu.tick(8);
+  assert_eq!(setup.memory.apu.noise.lfsr, 0x3FFF);
+  assert_eq!(setup.memory.apu.noise.output(), 0);
+  setup.memory.apu.tick(8 * 14);
+  assert_eq!(setup.memory.apu.noise.lfsr, 0x4000);
+  assert_eq!(setup.memory.apu.noise.output(), 15);
+
+  // 15-bit mode repeats every 32767 clocks, 7-bit mode every 127
+  play_noise(&mut setup.memory, 0x00);
+  let bits = noise_bits(&mut setup.memory, 32767 * 2);
+  assert_eq!(bits[..32767], bits[32767..]);
+  assert_ne!(bits[..127], bits[127..254]);
+
+  play_noise(&mut setup.memory, 0x08);
+  setup.memory.apu.tick(8);
+  assert_eq!(setup.memory.apu.noise.lfsr, 0x3FBF);
+  let bits = noise_bits(&mut setup.memory, 127 * 2);
+  assert_eq!(bits[..127], bits[127..]);
+}
+
+#[test]
+fn test_noise_clock_shift_and_divisor() {
+  let mut setup = Setup::new();
+
+  // Divisor code 1 is 16 T-cycles, shifted left by 2
+  play_noise(&mut setup.memory, 0x21);
+  setup.memory.apu.tick(63);
+  assert_eq!(setup.memory.apu.noise.lfsr, 0x7FFF);
+  setup.memory.apu.tick(1);
+  assert_eq!(setup.memory.apu.noise.lfsr, 0x3FFF);
+
+  // Shifts of 14 and 15 never clock the LFSR
+  play_noise(&mut setup.memory, 0xE0);
+  setup.memory.apu.tick(8 << 15);
+  assert_eq!(setup.memory.apu.noise.lfsr, 0x7FFF);
+  assert_eq!(setup.memory.read(0xFF22), 0xE0);
+}
+
+#[test]
+fn test_noise_envelope_and_length() {
+  let mut setup = Setup::new();
+
+  // DAC off keeps the channel from starting
+  setup.memory.write(0xFF21, 0x00);
+  setup.memory.write(0xFF23, 0x80);
+  assert!(!setup.memory.apu.noise.enabled);
+
+  // Decreasing from 2 every envelope step
+  setup.memory.write(0xFF21, 0x21);
+  setup.memory.write(0xFF20, 0x3E);
+  setup.memory.write(0xFF23, 0xC0);
+  assert_eq!(setup.memory.apu.noise.envelope.volume, 2);
+  assert_eq!(setup.memory.read(0xFF23), 0xFF);
+  assert_eq!(setup.memory.read(0xFF20), 0xFF);
+
+  // Length 64 - 0x3E leaves 2 clocks
+  setup.memory.tick(FRAME_STEP * 2);
+  assert!(setup.memory.apu.noise.enabled);
+  setup.memory.tick(FRAME_STEP * 2);
+  assert!(!setup.memory.apu.noise.enabled);
+
+  setup.memory.write(0xFF23, 0x80);
+  setup.memory.tick(FRAME_STEP * 8);
+  assert_eq!(setup.memory.apu.noise.envelope.volume, 1);
+  setup.memory.tick(FRAME_STEP * 8);
+  assert_eq!(setup.memory.apu.noise.envelope.volume, 0);
+}
+
+// Channel 2 playing with one length clock left
+fn play_last_length_clock(memory: &mut Memory) {
+  memory.write(0xFF17, 0xF0);
+  memory.write(0xFF16, 0x3F);
+  memory.write(0xFF19, 0xC0);
+}
+
+#[test]
+fn test_div_write_clocks_frame_sequencer() {
+  let mut setup = Setup::new();
+  play_last_length_clock(&mut setup.memory);
+
+  // Resetting DIV while bit 12 is set is a falling edge
+  setup.memory.tick(4096);
+  assert!(setup.memory.apu.square2.enabled);
+  setup.memory.write(0xFF04, 0x00);
+  assert!(!setup.memory.apu.square2.enabled);
+
+  // Resetting it before the bit is set pushes the next step back
+  let mut setup = Setup::new();
+  play_last_length_clock(&mut setup.memory);
+
+  setup.memory.tick(4000);
+  setup.memory.write(0xFF04, 0x00);
+  setup.memory.tick(FRAME_STEP - 4);
+  assert!(setup.memory.apu.square2.enabled);
+  setup.memory.tick(4);
+  assert!(!setup.memory.apu.square2.enabled);
+}
+
+#[test]
+fn test_double_speed_frame_sequencer_bit() {
+  let mut setup = Setup::new();
+  setup.memory.timer.double_speed = true;
+  play_last_length_clock(&mut setup.memory);
+
+  setup.memory.tick(FRAME_STEP);
+  assert!(setup.memory.apu.square2.enabled);
+  setup.memory.tick(FRAME_STEP);
+  assert!(!setup.memory.apu.square2.enabled);
+}
+
+#[test]
+fn test_power_off_clears_registers() {
+  let mut setup = Setup::new();
+  setup.memory.write(0xFF30, 0x12);
+  setup.memory.write(0xFF24, 0x77);
+  setup.memory.write(0xFF17, 0xF0);
+  setup.memory.write(0xFF16, 0x80 | 0x30);
+  setup.memory.write(0xFF19, 0x80);
+  assert_eq!(setup.memory.read(0xFF26), 0xF2);
+
+  setup.memory.write(0xFF26, 0x00);
+  assert_eq!(setup.memory.read(0xFF26), 0x70);
+  assert_eq!(setup.memory.read(0xFF24), 0x00);
+  assert_eq!(setup.memory.read(0xFF17), 0x00);
+  assert_eq!(setup.memory.read(0xFF16), 0x3F);
+  assert_eq!(setup.memory.read(0xFF30), 0x12);
+
+  // Writes are ignored while off, except wave RAM and DMG length counters
+  setup.memory.write(0xFF24, 0x77);
+  setup.memory.write(0xFF30, 0x34);
+  setup.memory.write(0xFF16, 0xBE);
+  assert_eq!(setup.memory.read(0xFF24), 0x00);
+  assert_eq!(setup.memory.read(0xFF30), 0x34);
+  assert_eq!(setup.memory.read(0xFF16), 0x3F);
+  assert_eq!(setup.memory.apu.square2.length.counter, 2);
+
+  setup.memory.write(0xFF26, 0x80);
+  setup.memory.write(0xFF24, 0x77);
+  assert_eq!(setup.memory.read(0xFF24), 0x77);
+}
+
+#[test]
+fn test_stereo_mix() {
+  let mut setup = Setup::new();
+
+  // Channel 2 high at full volume
+  setup.memory.write(0xFF16, 0x80);
+  setup.memory.write(0xFF17, 0xF0);
+  setup.memory.write(0xFF18, 0xFF);
+  setup.memory.write(0xFF19, 0x87);
+  setup.memory.apu.tick(20);
+
+  // Right only, at full right volume
+  setup.memory.write(0xFF25, 0x02);
+  setup.memory.write(0xFF24, 0x07);
+  assert_eq!(setup.memory.apu.mix(), (0.0, 0.25));
+
+  // Left only, at half volume
+  setup.memory.write(0xFF25, 0x20);
+  setup.memory.write(0xFF24, 0x30);
+  assert_eq!(setup.memory.apu.mix(), (0.125, 0.0));
+
+  setup.memory.write(0xFF26, 0x00);
+  assert_eq!(setup.memory.apu.mix(), (0.0, 0.0));
+}
+
+#[test]
+fn test_sample_stream_rate() {
+  let mut setup = Setup::new();
+  setup.memory.apu.set_sample_rate(44100);
+
+  for _ in 0..4194304 / 16 {
+    setup.memory.apu.tick(16);
+  }
+
+  assert_eq!(setup.memory.apu.take_samples().len(), 44100);
+  assert!(setup.memory.apu.take_samples().is_empty());
+}
+
+#[test]
+fn test_cgb_pcm_registers() {
+  let mut setup = Setup::new();
+  setup.memory.ppu.cgb = true;
+
+  setup.memory.write(0xFF16, 0x80);
+  setup.memory.write(0xFF17, 0xA0);
+  setup.memory.write(0xFF18, 0xFF);
+  setup.memory.write(0xFF19, 0x87);
+  setup.memory.apu.tick(20);
+
+  assert_eq!(setup.memory.read(0xFF76), 0xA0);
+  assert_eq!(setup.memory.read(0xFF77), 0x00);
+}
+
+#[test]
+fn test_blip_step_settles_at_level() {
+  let mut blip = BlipBuffer::new(4194304, 44100);
+  blip.add_delta(1000, 0.5);
+  blip.end_frame(4194304);
+
+  let mut samples = Vec::new();
+  blip.read_samples(&mut samples);
+  assert_eq!(samples.len(), 44100);
+
+  // Silent before the step, at the new level once the kernel has passed
+  assert_eq!(samples[0], 0.0);
+  assert!(samples[40..].iter().all(|sample| (sample - 0.5).abs() < 1e-4));
+}
+
+#[test]
+fn test_blip_filters_out_ultrasonic_square() {
+  let mut blip = BlipBuffer::new(4194304, 48000);
+
+  // 131072 Hz square wave, far above what 48 kHz can carry
+  let mut level = 0.0;
+  for clock in 0..4194304 / 100 {
+    if clock % 16 == 0 {
+      let next = if level == 0.0 { 1.0 } else { 0.0 };
+      blip.add_delta(clock, next - level);
+      level = next;
+    }
+  }
+  blip.end_frame(4194304 / 100);
+
+  let mut samples = Vec::new();
+  blip.read_samples(&mut samples);
+
+  // Only its average comes through, where point sampling would alias
+  assert!(samples[40..].iter().all(|sample| (sample - 0.5).abs() < 0.05));
+}
+
+#[test]
+fn test_high_pass_removes_dc() {
+  let mut dmg = HighPass::new(4194304, 44100, false);
+  let mut cgb = HighPass::new(4194304, 44100, true);
+
+  assert_eq!(dmg.filter(1.0), 1.0);
+  let dmg_level = (0..4410).map(|_| dmg.filter(1.0)).last().unwrap();
+  let cgb_level = (0..4410).map(|_| cgb.filter(1.0)).last().unwrap();
+
+  // The CGB capacitor discharges faster
+  assert!(dmg_level < 0.01);
+  assert!(cgb_level < dmg_level);
+}
+
+#[test]
+fn test_steps_land_on_their_cycle() {
+  let mut whole = Setup::new();
+  let mut split = Setup::new();
+
+  for setup in [&mut whole, &mut split] {
+    setup.memory.apu.set_sample_rate(44100);
+    setup.memory.write(0xFF17, 0xF0);
+    setup.memory.write(0xFF18, 0x00);
+    setup.memory.write(0xFF19, 0x87);
+  }
+
+  // Steps every 1024 cycles fall inside the 100-cycle ticks
+  for _ in 0..4410 {
+    whole.memory.apu.tick(100);
+    for _ in 0..100 {
+      split.memory.apu.tick(1);
+    }
+  }
+
+  let whole = whole.memory.apu.take_samples();
+  let split = split.memory.apu.take_samples();
+
+  assert_eq!(whole.len(), split.len());
+  for (a, b) in whole.iter().zip(split.iter()) {
+    assert!((a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4);
+  }
+}
+
+#[test]
+fn test_stems_add_up_to_mix() {
+  let mut setup = Setup::new();
+  setup.memory.apu.set_sample_rate(44100);
+  setup.memory.apu.enable_stems(44100);
+
+  // Channel 2 on the right and noise on both sides
+  setup.memory.write(0xFF25, 0x82);
+  setup.memory.write(0xFF16, 0x80);
+  setup.memory.write(0xFF17, 0xF0);
+  setup.memory.write(0xFF18, 0x00);
+  setup.memory.write(0xFF19, 0x87);
+  play_noise(&mut setup.memory, 0x41);
+
+  for _ in 0..4194304 / 100 / 4 {
+    setup.memory.tick(4);
+  }
+
+  let mix = setup.memory.apu.take_samples();
+  let stems = setup.memory.apu.take_stem_samples();
+  assert_eq!(stems.len(), 4);
+  assert!(stems.iter().all(|stem| stem.len() == mix.len()));
+
+  // Channels 1 and 3 are silent, channel 2 only on the right
+  assert!(stems[0].iter().chain(stems[2].iter()).all(|&sample| sample == (0.0, 0.0)));
+  assert!(stems[1].iter().all(|sample| sample.0 == 0.0));
+  assert!(stems[1].iter().any(|sample| sample.1 != 0.0));
+  assert!(stems[3].iter().any(|sample| sample.0 != 0.0));
+
+  for (i, sample) in mix.iter().enumerate() {
+    let left: f32 = stems.iter().map(|stem| stem[i].0).sum();
+    let right: f32 = stems.iter().map(|stem| stem[i].1).sum();
+    assert!((sample.0 - left).abs() < 1e-4);
+    assert!((sample.1 - right).abs() < 1e-4);
+  }
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+  u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+#[test]
+fn test_vgm_log() {
+  let mut setup = Setup::new();
+  setup.memory.start_vgm_log();
+
+  setup.memory.tick(4194304);
+  setup.memory.write(0xFF12, 0xF0);
+  setup.memory.tick(1522);
+  setup.memory.write(0xFF30, 0x12);
+  setup.memory.write(0xFF40, 0x91);
+
+  let file = setup.memory.vgm.as_mut().unwrap().bytes();
+
+  assert_eq!(&file[0..4], b"Vgm ");
+  assert_eq!(u32_at(&file, 0x04) as usize, file.len() - 4);
+  assert_eq!(u32_at(&file, 0x08), 0x161);
+  assert_eq!(u32_at(&file, 0x18), 44116);
+  assert_eq!(u32_at(&file, 0x34), 0x100 - 0x34);
+  assert_eq!(u32_at(&file, 0x80), 4194304);
+
+  // The start-up master registers, a second, a write, 16 samples, a write
+  assert_eq!(&file[0x100..], &[
+    0xB3, 0x16, 0xF0, 0xB3, 0x15, 0xF3, 0xB3, 0x14, 0x77,
+    0x61, 0x44, 0xAC,
+    0xB3, 0x02, 0xF0,
+    0x7F,
+    0xB3, 0x20, 0x12,
+    0x66,
+  ]);
+}
+
+// The events of a track of a MIDI file, after its name
+fn midi_track(file: &[u8], track: usize) -> &[u8] {
+  let mut offset = 14;
+  for _ in 0..track {
+    offset += 8 + u32::from_be_bytes([file[offset + 4], file[offset + 5], file[offset + 6], file[offset + 7]]) as usize;
+  }
+
+  let length = u32::from_be_bytes([file[offset + 4], file[offset + 5], file[offset + 6], file[offset + 7]]) as usize;
+  let track = &file[offset + 8..offset + 8 + length];
+  &track[4 + track[3] as usize..]
+}
+
+#[test]
+fn test_midi_square_notes() {
+  let mut setup = Setup::new();
+  setup.memory.midi = Some(MidiLog::new());
+
+  // A4 from a second in, retriggered half a second later and silenced by
+  // turning the DAC off after another half second
+  setup.memory.tick(4194304);
+  setup.memory.write(0xFF12, 0xF0);
+  setup.memory.write(0xFF13, 0xD6);
+  setup.memory.write(0xFF14, 0x86);
+  setup.memory.tick(4);
+  setup.memory.tick(2097152);
+  setup.memory.write(0xFF14, 0x86);
+  setup.memory.tick(4);
+  setup.memory.tick(2097152);
+  setup.memory.write(0xFF12, 0x00);
+  setup.memory.tick(4);
+
+  let file = setup.memory.midi.as_mut().unwrap().bytes();
+
+  assert_eq!(&file[0..14], &[b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 1, 0, 4, 0x01, 0xE0]);
+  assert_eq!(&file[14..18], b"MTrk");
+  assert_eq!(&file[22..34], &[0x00, 0xFF, 0x03, 8, b'S', b'q', b'u', b'a', b'r', b'e', b' ', b'1']);
+
+  assert_eq!(midi_track(&file, 0), &[
+    0x87, 0x40, 0xB0, 0x0B, 0x7F, 0x00, 0x90, 69, 0x7F,
+    0x83, 0x60, 0x80, 69, 0x00, 0x00, 0xB0, 0x0B, 0x7F, 0x00, 0x90, 69, 0x7F,
+    0x83, 0x60, 0x80, 69, 0x00,
+    0x00, 0xFF, 0x2F, 0x00,
+  ]);
+  assert_eq!(midi_track(&file, 1), &[0x00, 0xFF, 0x2F, 0x00]);
+}
+
+#[test]
+fn test_midi_noise_envelope() {
+  let mut setup = Setup::new();
+  setup.memory.midi = Some(MidiLog::new());
+
+  // A fast LFSR is a hi-hat, fading out one step per envelope clock
+  setup.memory.write(0xFF21, 0xF1);
+  setup.memory.write(0xFF22, 0x00);
+  setup.memory.write(0xFF23, 0x80);
+  for _ in 0..9 {
+    setup.memory.tick(FRAME_STEP);
+  }
+
+  let file = setup.memory.midi.as_mut().unwrap().bytes();
+  let events = midi_track(&file, 3);
+
+  assert_eq!(&events[0..8], &[0x01, 0xB9, 0x0B, 0x7F, 0x00, 0x99, 42, 0x7F]);
+  assert_eq!(&events[8..12], &[14, 0xB9, 0x0B, 118]);
+}
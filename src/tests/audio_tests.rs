@@ -0,0 +1,84 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use audio::audio::AudioSink;
+use audio::audio::RingBufferSink;
+use audio::wav::stem_path;
+use audio::wav::WavSink;
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+  u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+  u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+// Records the samples to a WAV file and reads the whole file back
+fn record(name: &str, sample_rate: u32, channels: u16, samples: &[(f32, f32)]) -> Vec<u8> {
+  let path = env::temp_dir().join(name);
+
+  let mut sink = WavSink::create(&path, sample_rate, channels).unwrap();
+  sink.write(samples).unwrap();
+  sink.finish().unwrap();
+  drop(sink);
+
+  let data = fs::read(&path).unwrap();
+  fs::remove_file(&path).unwrap();
+  data
+}
+
+#[test]
+fn test_ring_buffer_drops_oldest() {
+  let mut sink = RingBufferSink::new(4);
+  sink.write(&[(0.1, 0.1), (0.2, 0.2), (0.3, 0.3)]).unwrap();
+  sink.write(&[(0.4, 0.4), (0.5, 0.5)]).unwrap();
+  assert_eq!(sink.len(), 4);
+
+  let mut output = [(0.0, 0.0); 3];
+  assert_eq!(sink.read(&mut output), 3);
+  assert_eq!(output, [(0.2, 0.2), (0.3, 0.3), (0.4, 0.4)]);
+
+  assert_eq!(sink.read(&mut output), 1);
+  assert_eq!(output[0], (0.5, 0.5));
+  assert!(sink.is_empty());
+}
+
+#[test]
+fn test_wav_stereo_header_and_data() {
+  let data = record("clonelebi_stereo_test.wav", 48000, 2, &[(1.0, -1.0), (0.0, 2.0)]);
+
+  assert_eq!(&data[0..4], b"RIFF");
+  assert_eq!(u32_at(&data, 4), 36 + 8);
+  assert_eq!(&data[8..16], b"WAVEfmt ");
+  assert_eq!(u16_at(&data, 20), 1);
+  assert_eq!(u16_at(&data, 22), 2);
+  assert_eq!(u32_at(&data, 24), 48000);
+  assert_eq!(u32_at(&data, 28), 48000 * 4);
+  assert_eq!(u16_at(&data, 32), 4);
+  assert_eq!(u16_at(&data, 34), 16);
+  assert_eq!(&data[36..40], b"data");
+  assert_eq!(u32_at(&data, 40), 8);
+
+  // Out of range samples are clipped
+  let samples: Vec<i16> = data[44..].chunks(2).map(|pair| i16::from_le_bytes([pair[0], pair[1]])).collect();
+  assert_eq!(samples, vec![32767, -32767, 0, 32767]);
+}
+
+#[test]
+fn test_wav_mono_averages_sides() {
+  let data = record("clonelebi_mono_test.wav", 44100, 1, &[(0.5, -0.5), (1.0, 0.0)]);
+
+  assert_eq!(u16_at(&data, 22), 1);
+  assert_eq!(u32_at(&data, 28), 44100 * 2);
+  assert_eq!(u32_at(&data, 40), 4);
+  assert_eq!(data.len(), 48);
+  assert_eq!(i16::from_le_bytes([data[46], data[47]]), 16383);
+}
+
+#[test]
+fn test_stem_paths() {
+  assert_eq!(stem_path(Path::new("out/song.wav"), 1), Path::new("out/song.ch1.wav"));
+  assert_eq!(stem_path(Path::new("song"), 4), Path::new("song.ch4.wav"));
+}
@@ -0,0 +1,535 @@
+use std::env;
+use std::fs;
+
+use cartridge::camera_sensor::CameraSensor;
+use cartridge::camera_sensor::StillImage;
+use cartridge::cartridge::Cartridge;
+use cartridge::rtc;
+use cartridge::rtc::Rtc;
+use infrared::infrared::InfraredLink;
+
+struct Lamp;
+
+impl InfraredLink for Lamp {
+  fn set_led(&mut self, _on: bool) {}
+
+  fn receiving(&self) -> bool {
+    true
+  }
+}
+
+struct Setup {
+  cartridge: Cartridge
+}
+
+impl Setup {
+  pub fn new(cartridge_type: u8, rom_banks: usize) -> Self {
+    let mut rom = vec![0; rom_banks * 0x4000];
+
+    for (bank, chunk) in rom.chunks_mut(0x4000).enumerate() {
+      chunk[0] = bank as u8;
+      chunk[1] = (bank >> 8) as u8;
+    }
+
+    rom[0x0147] = cartridge_type;
+    rom[0x0149] = 0x03;
+
+    Self {
+      cartridge: Cartridge::new(rom),
+    }
+  }
+
+  // Clocks one bit into the MBC7 EEPROM and returns DO
+  fn eeprom_bit(&mut self, bit: u8) -> u8 {
+    self.cartridge.write(0xA080, 0x80 | (bit << 1));
+    self.cartridge.write(0xA080, 0xC0 | (bit << 1));
+    self.cartridge.read(0xA080) & 0x01
+  }
+
+  fn eeprom_command(&mut self, command: u16) {
+    self.cartridge.write(0xA080, 0x00);
+    self.eeprom_bit(1);
+
+    for i in (0..10).rev() {
+      self.eeprom_bit(((command >> i) & 1) as u8);
+    }
+  }
+
+  fn eeprom_read(&mut self, address: u16) -> u16 {
+    self.eeprom_command(0b10 << 8 | address);
+
+    let mut value = 0;
+    for _ in 0..16 {
+      value = (value << 1) | self.eeprom_bit(0) as u16;
+    }
+
+    self.cartridge.write(0xA080, 0x00);
+    value
+  }
+}
+
+#[test]
+fn test_mbc1_banking() {
+  let mut setup = Setup::new(0x03, 64);
+
+  setup.cartridge.write(0x2000, 0x00);
+  assert_eq!(setup.cartridge.read(0x4000), 0x01);
+
+  setup.cartridge.write(0x2000, 0x03);
+  setup.cartridge.write(0x4000, 0x01);
+  assert_eq!(setup.cartridge.read(0x4000), 0x23);
+
+  // Mode 1 maps the upper bits into 0x0000 and banks the RAM
+  setup.cartridge.write(0x6000, 0x01);
+  assert_eq!(setup.cartridge.read(0x0000), 0x20);
+
+  setup.cartridge.write(0x0000, 0x0A);
+  setup.cartridge.write(0xA000, 0x42);
+  setup.cartridge.write(0x4000, 0x00);
+  assert_eq!(setup.cartridge.read(0xA000), 0x00);
+  setup.cartridge.write(0x4000, 0x01);
+  assert_eq!(setup.cartridge.read(0xA000), 0x42);
+
+  setup.cartridge.write(0x0000, 0x00);
+  assert_eq!(setup.cartridge.read(0xA000), 0xFF);
+}
+
+#[test]
+fn test_mbc2_half_byte_ram() {
+  let mut setup = Setup::new(0x06, 8);
+
+  // Address bit 8 set selects the ROM bank
+  setup.cartridge.write(0x2100, 0x05);
+  assert_eq!(setup.cartridge.read(0x4000), 0x05);
+
+  setup.cartridge.write(0x0000, 0x0A);
+  setup.cartridge.write(0xA001, 0x5A);
+  assert_eq!(setup.cartridge.read(0xA001), 0xFA);
+  assert_eq!(setup.cartridge.read(0xA201), 0xFA);
+}
+
+#[test]
+fn test_mbc5_banking() {
+  let mut setup = Setup::new(0x1B, 0x200);
+
+  setup.cartridge.write(0x2000, 0x00);
+  assert_eq!(setup.cartridge.read(0x4000), 0x00);
+
+  setup.cartridge.write(0x2000, 0x05);
+  setup.cartridge.write(0x3000, 0x01);
+  assert_eq!(setup.cartridge.read(0x4000), 0x05);
+  assert_eq!(setup.cartridge.read(0x4001), 0x01);
+
+  setup.cartridge.write(0x0000, 0x0A);
+  setup.cartridge.write(0x4000, 0x03);
+  setup.cartridge.write(0xA000, 0x42);
+  setup.cartridge.write(0x4000, 0x00);
+  assert_eq!(setup.cartridge.read(0xA000), 0x00);
+  setup.cartridge.write(0x4000, 0x03);
+  assert_eq!(setup.cartridge.read(0xA000), 0x42);
+}
+
+#[test]
+fn test_mbc7_rom_banking() {
+  let mut setup = Setup::new(0x22, 8);
+
+  setup.cartridge.write(0x2000, 0x05);
+
+  assert_eq!(setup.cartridge.read(0x0000), 0x00);
+  assert_eq!(setup.cartridge.read(0x4000), 0x05);
+}
+
+#[test]
+fn test_mbc7_registers_need_both_enables() {
+  let mut setup = Setup::new(0x22, 2);
+
+  setup.cartridge.write(0x0000, 0x0A);
+  assert_eq!(setup.cartridge.read(0xA020), 0xFF);
+
+  setup.cartridge.write(0x4000, 0x40);
+  assert_eq!(setup.cartridge.read(0xA060), 0x00);
+}
+
+#[test]
+fn test_mbc7_accelerometer_latch() {
+  let mut setup = Setup::new(0x22, 2);
+
+  setup.cartridge.write(0x0000, 0x0A);
+  setup.cartridge.write(0x4000, 0x40);
+  setup.cartridge.set_tilt(1.0, -1.0);
+
+  // Latching without erasing first is ignored
+  setup.cartridge.write(0xA010, 0xAA);
+  assert_eq!(setup.cartridge.read(0xA030), 0x80);
+  assert_eq!(setup.cartridge.read(0xA020), 0x00);
+
+  setup.cartridge.write(0xA000, 0x55);
+  setup.cartridge.write(0xA010, 0xAA);
+
+  let x = (setup.cartridge.read(0xA030) as u16) << 8 | setup.cartridge.read(0xA020) as u16;
+  let y = (setup.cartridge.read(0xA050) as u16) << 8 | setup.cartridge.read(0xA040) as u16;
+
+  assert_eq!(x, 0x81D0 + 0x70);
+  assert_eq!(y, 0x81D0 - 0x70);
+}
+
+#[test]
+fn test_mbc7_eeprom_write_and_read() {
+  let mut setup = Setup::new(0x22, 2);
+
+  setup.cartridge.write(0x0000, 0x0A);
+  setup.cartridge.write(0x4000, 0x40);
+
+  // Writes are ignored until EWEN
+  setup.eeprom_command(0b01 << 8 | 0x12);
+  for i in (0..16).rev() {
+    setup.eeprom_bit(((0x1234 >> i) & 1) as u8);
+  }
+  assert_eq!(setup.eeprom_read(0x12), 0xFFFF);
+
+  setup.eeprom_command(0b00_11 << 6);
+  setup.eeprom_command(0b01 << 8 | 0x12);
+  for i in (0..16).rev() {
+    setup.eeprom_bit(((0xBEEF >> i) & 1) as u8);
+  }
+
+  assert_eq!(setup.eeprom_read(0x12), 0xBEEF);
+  assert_eq!(setup.eeprom_read(0x13), 0xFFFF);
+}
+
+#[test]
+fn test_huc1_infrared_and_ram() {
+  let mut setup = Setup::new(0xFF, 4);
+  setup.cartridge.connect_infrared(Box::new(Lamp));
+
+  setup.cartridge.write(0x4000, 0x01);
+  setup.cartridge.write(0xA000, 0x42);
+  setup.cartridge.write(0x4000, 0x00);
+  assert_eq!(setup.cartridge.read(0xA000), 0x00);
+
+  setup.cartridge.write(0x0000, 0x0E);
+  assert_eq!(setup.cartridge.read(0xA000), 0xC1);
+
+  setup.cartridge.write(0x0000, 0x00);
+  setup.cartridge.write(0x4000, 0x01);
+  assert_eq!(setup.cartridge.read(0xA000), 0x42);
+}
+
+#[test]
+fn test_huc3_rtc_commands() {
+  let mut setup = Setup::new(0xFE, 4);
+  setup.cartridge.write(0x0000, 0x0B);
+
+  // Write 0x123 minutes and 0x045 days to RTC memory 0x00-0x05
+  setup.cartridge.write(0xA000, 0x40);
+  setup.cartridge.write(0xA000, 0x50);
+  for nibble in [0x3, 0x2, 0x1, 0x5, 0x4, 0x0].iter() {
+    setup.cartridge.write(0xA000, 0x30 | nibble);
+  }
+
+  // Set the clock from memory, clear it, then copy the clock back
+  setup.cartridge.write(0xA000, 0x61);
+  setup.cartridge.write(0xA000, 0x40);
+  for _ in 0..6 {
+    setup.cartridge.write(0xA000, 0x30);
+  }
+  setup.cartridge.write(0xA000, 0x60);
+
+  setup.cartridge.write(0xA000, 0x40);
+  let mut nibbles = Vec::new();
+  for _ in 0..6 {
+    setup.cartridge.write(0x0000, 0x0B);
+    setup.cartridge.write(0xA000, 0x10);
+    setup.cartridge.write(0x0000, 0x0C);
+    nibbles.push(setup.cartridge.read(0xA000) & 0x0F);
+  }
+
+  assert_eq!(nibbles, vec![0x3, 0x2, 0x1, 0x5, 0x4, 0x0]);
+}
+
+#[test]
+fn test_huc3_speaker_tone() {
+  let mut setup = Setup::new(0xFE, 4);
+  setup.cartridge.write(0x0000, 0x0B);
+  assert_eq!(setup.cartridge.take_speaker_tone(), None);
+
+  // Store tone 0x9 at RTC memory 0x27, then play it
+  setup.cartridge.write(0xA000, 0x47);
+  setup.cartridge.write(0xA000, 0x52);
+  setup.cartridge.write(0xA000, 0x39);
+  setup.cartridge.write(0xA000, 0x6E);
+
+  assert_eq!(setup.cartridge.take_speaker_tone(), Some(0x9));
+  assert_eq!(setup.cartridge.take_speaker_tone(), None);
+}
+
+#[test]
+fn test_huc3_rtc_reads_do_not_save() {
+  let directory = env::temp_dir().join("clonelebi_huc3_save_test");
+  fs::create_dir_all(&directory).unwrap();
+  let rom_path = directory.join("huc3.gb");
+  let save_path = directory.join("huc3.sav");
+
+  let mut rom = vec![0; 0x8000];
+  rom[0x0147] = 0xFE;
+  rom[0x0149] = 0x02;
+  fs::write(&rom_path, &rom).unwrap();
+  let _ = fs::remove_file(&save_path);
+
+  let mut cartridge = Cartridge::from_file(&rom_path).unwrap();
+  cartridge.write(0x0000, 0x0B);
+  cartridge.write(0xA000, 0x40);
+  cartridge.write(0xA000, 0x10);
+  cartridge.write(0xA000, 0x60);
+  cartridge.write(0xA000, 0x62);
+  cartridge.flush().unwrap();
+  assert!(!save_path.exists());
+
+  // Setting the clock is kept by the battery
+  cartridge.write(0xA000, 0x61);
+  cartridge.flush().unwrap();
+  assert!(save_path.exists());
+
+  drop(cartridge);
+  fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_camera_capture() {
+  let mut setup = Setup::new(0xFC, 4);
+
+  // Left half black, right half white
+  let grey: Vec<u8> = (0..128 * 112).map(|i| if i % 128 < 64 { 0x00 } else { 0xFF }).collect();
+  setup.cartridge.set_camera_sensor(Box::new(StillImage::from_grey(128, 112, &grey)));
+
+  setup.cartridge.write(0x0000, 0x0A);
+  setup.cartridge.write(0x4000, 0x10);
+  setup.cartridge.write(0xA002, 0x10);
+  setup.cartridge.write(0xA003, 0x00);
+  for register in 0xA006..0xA036 {
+    setup.cartridge.write(register, 0x80);
+  }
+
+  setup.cartridge.write(0xA000, 0x01);
+  assert_eq!(setup.cartridge.read(0xA000) & 0x01, 0x01);
+
+  setup.cartridge.tick(1_000_000);
+  assert_eq!(setup.cartridge.read(0xA000) & 0x01, 0x00);
+
+  setup.cartridge.write(0x4000, 0x00);
+
+  // First tile is black, the last tile of the first row is white
+  assert_eq!(setup.cartridge.read(0xA100), 0xFF);
+  assert_eq!(setup.cartridge.read(0xA101), 0xFF);
+  assert_eq!(setup.cartridge.read(0xA100 + 15 * 16), 0x00);
+  assert_eq!(setup.cartridge.read(0xA101 + 15 * 16), 0x00);
+}
+
+#[test]
+fn test_camera_pgm_image() {
+  let path = env::temp_dir().join("clonelebi_camera_test.pgm");
+  fs::write(&path, "P2\n# two by one\n2 1\n255\n0 255\n").unwrap();
+
+  let mut image = StillImage::from_file(&path).unwrap();
+  let pixels = image.capture();
+  fs::remove_file(&path).unwrap();
+
+  assert_eq!(pixels[0], 0x00);
+  assert_eq!(pixels[127], 0xFF);
+}
+
+#[test]
+fn test_camera_pgm_size_overflow() {
+  let path = env::temp_dir().join("clonelebi_camera_overflow_test.pgm");
+  fs::write(&path, format!("P5\n{} 2\n255\n", usize::MAX)).unwrap();
+
+  let result = StillImage::from_file(&path);
+  fs::remove_file(&path).unwrap();
+
+  assert!(result.is_err());
+}
+
+#[test]
+fn test_camera_photo_marks_ram_dirty() {
+  let directory = env::temp_dir().join("clonelebi_camera_save_test");
+  fs::create_dir_all(&directory).unwrap();
+  let rom_path = directory.join("camera.gb");
+  let save_path = directory.join("camera.sav");
+
+  let mut rom = vec![0; 0x8000];
+  rom[0x0147] = 0xFC;
+  fs::write(&rom_path, &rom).unwrap();
+  let _ = fs::remove_file(&save_path);
+
+  let mut cartridge = Cartridge::from_file(&rom_path).unwrap();
+  cartridge.set_camera_sensor(Box::new(StillImage::from_grey(128, 112, &[0x00; 128 * 112])));
+
+  cartridge.write(0x0000, 0x0A);
+  cartridge.write(0x4000, 0x10);
+  cartridge.write(0xA002, 0x10);
+  cartridge.write(0xA003, 0x00);
+  for register in 0xA006..0xA036 {
+    cartridge.write(register, 0x80);
+  }
+  cartridge.write(0xA000, 0x01);
+  cartridge.flush().unwrap();
+  assert!(!save_path.exists());
+
+  // The photo lands in RAM without the CPU writing to it
+  cartridge.tick(1_000_000);
+  cartridge.flush().unwrap();
+
+  let save = fs::read(&save_path).unwrap();
+  assert_eq!(save[0x100], 0xFF);
+
+  drop(cartridge);
+  fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_battery_ram_round_trip() {
+  let directory = env::temp_dir().join("clonelebi_save_test");
+  fs::create_dir_all(&directory).unwrap();
+  let rom_path = directory.join("game.gb");
+  let save_path = directory.join("game.sav");
+
+  let mut rom = vec![0; 0x8000];
+  rom[0x0147] = 0xFF;
+  rom[0x0149] = 0x02;
+  fs::write(&rom_path, &rom).unwrap();
+  let _ = fs::remove_file(&save_path);
+
+  {
+    let mut cartridge = Cartridge::from_file(&rom_path).unwrap();
+    cartridge.write(0xA010, 0x5A);
+    cartridge.flush().unwrap();
+  }
+
+  let save = fs::read(&save_path).unwrap();
+  assert_eq!(save.len(), 0x2000);
+  assert_eq!(save[0x10], 0x5A);
+  assert!(!directory.join("game.sav.tmp").exists());
+
+  let cartridge = Cartridge::from_file(&rom_path).unwrap();
+  assert_eq!(cartridge.read(0xA010), 0x5A);
+
+  fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_rom_ram_battery_saves() {
+  let directory = env::temp_dir().join("clonelebi_rom_ram_test");
+  fs::create_dir_all(&directory).unwrap();
+  let rom_path = directory.join("game.gb");
+  let save_path = directory.join("game.sav");
+
+  let mut rom = vec![0; 0x8000];
+  rom[0x0147] = 0x09;
+  rom[0x0149] = 0x02;
+  fs::write(&rom_path, &rom).unwrap();
+  let _ = fs::remove_file(&save_path);
+
+  {
+    let mut cartridge = Cartridge::from_file(&rom_path).unwrap();
+    cartridge.write(0xA010, 0x5A);
+  }
+
+  let cartridge = Cartridge::from_file(&rom_path).unwrap();
+  assert_eq!(cartridge.read(0xA010), 0x5A);
+
+  fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_same_rom_saves_apart() {
+  let directory = env::temp_dir().join("clonelebi_save_base_test");
+  fs::create_dir_all(&directory).unwrap();
+  let rom_path = directory.join("game.gb");
+  let save_base = directory.join("game-link");
+
+  let mut rom = vec![0; 0x8000];
+  rom[0x0147] = 0x09;
+  rom[0x0149] = 0x02;
+  fs::write(&rom_path, &rom).unwrap();
+
+  {
+    let mut first = Cartridge::from_file(&rom_path).unwrap();
+    let mut second = Cartridge::from_file_saving_to(&rom_path, &save_base).unwrap();
+    first.write(0xA000, 0x01);
+    second.write(0xA000, 0x02);
+  }
+
+  assert_eq!(fs::read(directory.join("game.sav")).unwrap()[0], 0x01);
+  assert_eq!(fs::read(directory.join("game-link.sav")).unwrap()[0], 0x02);
+
+  fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_disabled_ram_writes_do_not_save() {
+  let directory = env::temp_dir().join("clonelebi_disabled_ram_test");
+  fs::create_dir_all(&directory).unwrap();
+  let rom_path = directory.join("game.gb");
+  let save_path = directory.join("game.sav");
+
+  let mut rom = vec![0; 0x8000];
+  rom[0x0147] = 0x13;
+  rom[0x0149] = 0x02;
+  fs::write(&rom_path, &rom).unwrap();
+  let _ = fs::remove_file(&save_path);
+
+  let mut cartridge = Cartridge::from_file(&rom_path).unwrap();
+  cartridge.write(0xA000, 0x5A);
+  cartridge.flush().unwrap();
+  assert!(!save_path.exists());
+
+  cartridge.write(0x0000, 0x0A);
+  cartridge.write(0xA000, 0x5A);
+  cartridge.flush().unwrap();
+  assert_eq!(fs::read(&save_path).unwrap()[0], 0x5A);
+
+  drop(cartridge);
+  fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_mbc3_rtc_latch() {
+  let mut setup = Setup::new(0x10, 4);
+
+  setup.cartridge.write(0x0000, 0x0A);
+  setup.cartridge.write(0x4000, 0x0C);
+  setup.cartridge.write(0xA000, 0x40);
+  setup.cartridge.write(0x4000, 0x0A);
+  setup.cartridge.write(0xA000, 0x17);
+
+  // Nothing is visible until the clock is latched
+  assert_eq!(setup.cartridge.read(0xA000), 0x00);
+
+  setup.cartridge.write(0x6000, 0x00);
+  setup.cartridge.write(0x6000, 0x01);
+  assert_eq!(setup.cartridge.read(0xA000), 0x17);
+
+  setup.cartridge.write(0x4000, 0x0C);
+  assert_eq!(setup.cartridge.read(0xA000), 0x40);
+}
+
+#[test]
+fn test_rtc_footer_fast_forwards() {
+  let mut rtc = Rtc::new(0x200);
+  rtc.hours = 23;
+  rtc.days = 0x1FF;
+
+  let mut footer = rtc.footer();
+  assert_eq!(footer.len(), 48);
+
+  // Pretend the save was written two hours ago
+  let timestamp = rtc::unix_time() - 2 * 3600;
+  footer[40..48].copy_from_slice(&timestamp.to_le_bytes());
+
+  let mut loaded = Rtc::new(0x200);
+  assert!(loaded.load_footer(&footer));
+
+  assert_eq!(loaded.hours, 1);
+  assert_eq!(loaded.days, 0);
+  assert!(loaded.day_carry);
+}
@@ -0,0 +1,161 @@
+use audio::audio::AudioSink;
+use audio::audio::RingBufferSink;
+use gbs::gbs::Gbs;
+use gbs::player::GbsPlayer;
+
+const LOAD_ADDRESS: u16 = 0x0400;
+
+// A driver whose init stores the song index at 0xC000 and starts channel 2,
+// and whose play counts its calls at 0xC001
+fn driver() -> Vec<u8> {
+  vec![
+    // init
+    0xEA, 0x00, 0xC0, // LD (0xC000),A
+    0x3E, 0xF0, 0xE0, 0x17, // LD A,0xF0; LDH (0x17),A
+    0x3E, 0x80, 0xE0, 0x19, // LD A,0x80; LDH (0x19),A
+    0xC9, // RET
+    // play
+    0xFA, 0x01, 0xC0, // LD A,(0xC001)
+    0x3C, // INC A
+    0xEA, 0x01, 0xC0, // LD (0xC001),A
+    0xC9, // RET
+  ]
+}
+
+fn gbs_file(timer_modulo: u8, timer_control: u8, data: &[u8]) -> Vec<u8> {
+  let mut file = vec![0; 0x70];
+  file[0..3].copy_from_slice(b"GBS");
+  file[0x03] = 1;
+  file[0x04] = 3;
+  file[0x05] = 2;
+  file[0x06..0x08].copy_from_slice(&LOAD_ADDRESS.to_le_bytes());
+  file[0x08..0x0A].copy_from_slice(&LOAD_ADDRESS.to_le_bytes());
+  file[0x0A..0x0C].copy_from_slice(&(LOAD_ADDRESS + 12).to_le_bytes());
+  file[0x0C..0x0E].copy_from_slice(&0xDFFFu16.to_le_bytes());
+  file[0x0E] = timer_modulo;
+  file[0x0F] = timer_control;
+  file[0x10..0x15].copy_from_slice(b"Title");
+  file[0x30..0x36].copy_from_slice(b"Author");
+  file[0x50..0x54].copy_from_slice(b"2024");
+  file.extend_from_slice(data);
+  file
+}
+
+fn play_calls(player: &GbsPlayer) -> u8 {
+  player.memory.read(0xC001)
+}
+
+#[test]
+fn test_gbs_header() {
+  let gbs = Gbs::parse(&gbs_file(0xC0, 0x04, &driver())).unwrap();
+
+  assert_eq!(gbs.song_count, 3);
+  assert_eq!(gbs.first_song, 2);
+  assert_eq!(gbs.load_address, LOAD_ADDRESS);
+  assert_eq!(gbs.init_address, LOAD_ADDRESS);
+  assert_eq!(gbs.play_address, LOAD_ADDRESS + 12);
+  assert_eq!(gbs.stack_pointer, 0xDFFF);
+  assert!(gbs.uses_timer());
+  assert!(!gbs.double_speed());
+  assert_eq!(gbs.title, "Title");
+  assert_eq!(gbs.author, "Author");
+  assert_eq!(gbs.copyright, "2024");
+  assert_eq!(gbs.data, driver());
+
+  let mut file = gbs_file(0, 0, &driver());
+  file[0] = b'X';
+  assert!(Gbs::parse(&file).is_err());
+  assert!(Gbs::parse(&file[..0x20]).is_err());
+}
+
+#[test]
+fn test_gbs_rom_layout_and_banks() {
+  // Data running into bank 2, with RST vectors sent to the load address
+  let mut data = driver();
+  data.resize(0x8000 - LOAD_ADDRESS as usize + 1, 0);
+  data[0x8000 - LOAD_ADDRESS as usize] = 0x42;
+  let gbs = Gbs::parse(&gbs_file(0, 0, &data)).unwrap();
+
+  let rom = gbs.rom();
+  assert_eq!(rom.len(), 0xC000);
+  assert_eq!(&rom[0x38..0x3B], &[0xC3, 0x38, 0x04]);
+  assert_eq!(rom[LOAD_ADDRESS as usize], 0xEA);
+
+  let mut player = GbsPlayer::new(gbs);
+  player.start_song(1).unwrap();
+  player.memory.write(0x2000, 2);
+  assert_eq!(player.memory.read(0x4000), 0x42);
+
+  // Cartridge RAM is there without enabling it
+  player.memory.write(0xA000, 0x99);
+  assert_eq!(player.memory.read(0xA000), 0x99);
+}
+
+#[test]
+fn test_gbs_init_gets_song_index() {
+  let gbs = Gbs::parse(&gbs_file(0, 0, &driver())).unwrap();
+  let mut player = GbsPlayer::new(gbs);
+
+  player.start_song(3).unwrap();
+  assert_eq!(player.memory.read(0xC000), 2);
+  assert!(player.memory.apu.square2.enabled);
+  assert_eq!(player.cpu.registers.sp, 0xDFFF);
+  assert_eq!(play_calls(&player), 0);
+}
+
+#[test]
+fn test_gbs_rejects_missing_tracks() {
+  let gbs = Gbs::parse(&gbs_file(0, 0, &driver())).unwrap();
+  let mut player = GbsPlayer::new(gbs);
+
+  assert!(player.start_song(0).is_err());
+  assert!(player.start_song(4).is_err());
+  assert_eq!(player.memory.read(0xC000), 0x00);
+}
+
+#[test]
+fn test_gbs_vblank_driven_play() {
+  let gbs = Gbs::parse(&gbs_file(0, 0, &driver())).unwrap();
+  let mut player = GbsPlayer::new(gbs);
+  player.start_song(1).unwrap();
+
+  // 59.7 frames a second
+  player.render(1, |_| {}).unwrap();
+  assert_eq!(play_calls(&player), 59);
+}
+
+#[test]
+fn test_gbs_timer_driven_play() {
+  // 4096 Hz divided by 256 - 0xC0 is 64 Hz
+  let gbs = Gbs::parse(&gbs_file(0xC0, 0x04, &driver())).unwrap();
+  let mut player = GbsPlayer::new(gbs);
+  player.start_song(1).unwrap();
+
+  player.render(1, |_| {}).unwrap();
+  assert_eq!(play_calls(&player), 64);
+}
+
+#[test]
+fn test_gbs_renders_to_sink() {
+  let gbs = Gbs::parse(&gbs_file(0, 0, &driver())).unwrap();
+  let mut player = GbsPlayer::new(gbs);
+  player.start_song(1).unwrap();
+  player.memory.apu.set_sample_rate(44100);
+
+  let mut sink = RingBufferSink::new(44100 * 2);
+  player.render(1, |memory| sink.write(&memory.apu.take_samples()).unwrap()).unwrap();
+
+  assert!(sink.len() >= 44100);
+  let mut samples = vec![(0.0, 0.0); sink.len()];
+  sink.read(&mut samples);
+  assert!(samples.iter().any(|&(left, right)| left != 0.0 || right != 0.0));
+}
+
+#[test]
+fn test_gbs_hanging_routine_times_out() {
+  // JR -2 forever
+  let gbs = Gbs::parse(&gbs_file(0, 0, &[0x18, 0xFE])).unwrap();
+  let mut player = GbsPlayer::new(gbs);
+
+  assert!(player.start_song(1).is_err());
+}
@@ -4,6 +4,7 @@ use cpu::prefixed_instructions::PrefixedOptable;
 use cpu::registers::Registers;
 use cpu::registers::RegisterPair;
 use cpu::cpu::Cpu;
+use helpers::bit_operations;
 use memory::memory::Memory;
 
 
@@ -20,7 +21,8 @@ impl Setup {
         optable: Optable::new(),
         prefixed_optable: PrefixedOptable::new(),
         cycles: 0,
-        cycles_table: CycleTable::new()
+        cycles_table: CycleTable::new(),
+        stopped: false,
       },
       memory: Memory::new()
     }
@@ -183,17 +185,16 @@ fn test_ld_hl_n() {
 
 #[test]
 fn test_get_half_carry() {
-  let mut setup = Setup::new();
-
+  // 0x0F + 0x01 = 0x10 carries out of bit 3
   let mut prev = 0x0F;
-  let mut result = 0x10;
-  let mut half_carry = setup.cpu.get_half_carry(prev, result);
+  let mut n = 0x01;
+  let mut half_carry = bit_operations::get_half_carry(prev, n);
 
   assert!(half_carry);
 
   prev = 0x00;
-  result = 0x01;
-  half_carry = setup.cpu.get_half_carry(prev, result);
+  n = 0x01;
+  half_carry = bit_operations::get_half_carry(prev, n);
 
   assert_eq!(half_carry, false);
 }
@@ -0,0 +1,93 @@
+use cpu::cpu::Cpu;
+use joypad::joypad::Button;
+use memory::memory::Memory;
+
+struct Setup {
+  memory: Memory
+}
+
+impl Setup {
+  pub fn new() -> Self {
+    Self {
+      memory: Memory::new(),
+    }
+  }
+
+  fn joypad_interrupt(&self) -> bool {
+    self.memory.read(0xFF0F) & 0x10 != 0
+  }
+}
+
+#[test]
+fn test_joypad_select_matrix() {
+  let mut setup = Setup::new();
+
+  setup.memory.set_button(Button::Down, true);
+  setup.memory.set_button(Button::A, true);
+  setup.memory.set_button(Button::Start, true);
+
+  // Nothing selected
+  assert_eq!(setup.memory.read(0xFF00), 0xFF);
+
+  setup.memory.write(0xFF00, 0x20);
+  assert_eq!(setup.memory.read(0xFF00), 0xE7);
+
+  setup.memory.write(0xFF00, 0x10);
+  assert_eq!(setup.memory.read(0xFF00), 0xD6);
+
+  // Both rows at once
+  setup.memory.write(0xFF00, 0x00);
+  assert_eq!(setup.memory.read(0xFF00), 0xC6);
+
+  setup.memory.set_button(Button::A, false);
+  assert_eq!(setup.memory.read(0xFF00), 0xC7);
+}
+
+#[test]
+fn test_joypad_interrupt_on_falling_edge() {
+  let mut setup = Setup::new();
+  setup.memory.write(0xFF00, 0x10);
+
+  // Directions are not selected
+  setup.memory.set_button(Button::Left, true);
+  assert!(!setup.joypad_interrupt());
+
+  setup.memory.set_button(Button::B, true);
+  assert!(setup.joypad_interrupt());
+
+  // Releasing does not fire
+  setup.memory.write(0xFF0F, 0x00);
+  setup.memory.set_button(Button::B, false);
+  assert!(!setup.joypad_interrupt());
+
+  // Selecting a row with a held button pulls a line low too
+  setup.memory.write(0xFF00, 0x20);
+  assert!(setup.joypad_interrupt());
+}
+
+#[test]
+fn test_button_wakes_cpu_from_stop() {
+  let mut setup = Setup::new();
+  let mut cpu = Cpu::new();
+
+  setup.memory.write(0x0000, 0x10);
+  setup.memory.write(0x0002, 0x00);
+  setup.memory.write(0xFF00, 0x10);
+  setup.memory.tick(1024);
+
+  cpu.run_instruction(&mut setup.memory);
+  assert!(cpu.stopped);
+  // STOP resets DIV
+  assert_eq!(setup.memory.read(0xFF04), 0);
+
+  // The clock stands still
+  let cycles = cpu.cycles;
+  cpu.run_instruction(&mut setup.memory);
+  assert_eq!(cpu.cycles, cycles);
+  assert_eq!(cpu.registers.pc, 0x0002);
+
+  setup.memory.set_button(Button::Start, true);
+  cpu.run_instruction(&mut setup.memory);
+  assert!(!cpu.stopped);
+  assert_eq!(cpu.registers.pc, 0x0003);
+}
@@ -0,0 +1,250 @@
+use cpu::cpu::Cpu;
+use infrared::infrared::Loopback;
+use joypad::joypad::Button;
+use memory::memory::Memory;
+
+struct Setup {
+  memory: Memory
+}
+
+impl Setup {
+  pub fn new() -> Self {
+    Self {
+      memory: Memory::new(),
+    }
+  }
+}
+
+#[test]
+fn test_oam_dma_copies_one_byte_per_m_cycle() {
+  let mut setup = Setup::new();
+
+  for i in 0..0xA0 {
+    setup.memory.write(0xC000 + i, i as u8 + 1);
+  }
+  setup.memory.write(0xFF46, 0xC0);
+  assert_eq!(setup.memory.read(0xFF46), 0xC0);
+
+  // One M-cycle of setup, then 80 bytes
+  setup.memory.tick(4 + 80 * 4);
+  assert_eq!(setup.memory.ppu.oam[79], 80);
+  assert_eq!(setup.memory.ppu.oam[80], 0x00);
+
+  setup.memory.tick(80 * 4);
+  assert_eq!(setup.memory.ppu.oam[159], 160);
+  assert_eq!(setup.memory.read(0xFE00), 1);
+}
+
+#[test]
+fn test_oam_dma_bus_restrictions() {
+  let mut setup = Setup::new();
+
+  setup.memory.write(0xC010, 0x42);
+  setup.memory.write(0x8000, 0x24);
+  setup.memory.write(0xFF80, 0x99);
+  setup.memory.write(0xFF46, 0xC0);
+
+  // The setup M-cycle leaves the bus alone
+  assert_eq!(setup.memory.read(0xC010), 0x42);
+
+  setup.memory.tick(4 + 0x11 * 4);
+
+  // Only HRAM and I/O are reachable
+  assert_eq!(setup.memory.read(0xFF80), 0x99);
+  assert_eq!(setup.memory.read(0xFF46), 0xC0);
+  assert_eq!(setup.memory.read(0x0150), 0x42);
+  assert_eq!(setup.memory.read(0x8000), 0xFF);
+  assert_eq!(setup.memory.read(0xFE00), 0xFF);
+
+  setup.memory.write(0xC020, 0x55);
+  setup.memory.write(0xFF81, 0x66);
+  assert_eq!(setup.memory.read(0xFF81), 0x66);
+
+  setup.memory.tick(160 * 4);
+  assert_eq!(setup.memory.read(0xC020), 0x00);
+  assert_eq!(setup.memory.read(0x8000), 0x24);
+  assert_eq!(setup.memory.read(0xFE10), 0x42);
+}
+
+#[test]
+fn test_oam_dma_from_echo_ram() {
+  let mut setup = Setup::new();
+
+  setup.memory.write(0xDE05, 0x77);
+  setup.memory.write(0xFF46, 0xFE);
+  setup.memory.tick(161 * 4);
+
+  assert_eq!(setup.memory.ppu.oam[5], 0x77);
+}
+
+fn cgb_setup() -> Setup {
+  let mut setup = Setup::new();
+  setup.memory.ppu.cgb = true;
+
+  for i in 0..0x100 {
+    setup.memory.write(0xC000 + i, i as u8);
+  }
+
+  // Source 0xC000, destination 0x8800
+  setup.memory.write(0xFF51, 0xC0);
+  setup.memory.write(0xFF52, 0x00);
+  setup.memory.write(0xFF53, 0x08);
+  setup.memory.write(0xFF54, 0x00);
+  setup
+}
+
+#[test]
+fn test_general_purpose_vram_dma() {
+  let mut setup = cgb_setup();
+
+  setup.memory.write(0xFF55, 0x03);
+
+  assert_eq!(setup.memory.read(0x8800), 0x00);
+  assert_eq!(setup.memory.read(0x883F), 0x3F);
+  assert_eq!(setup.memory.read(0x8840), 0x00);
+  assert_eq!(setup.memory.read(0xFF55), 0xFF);
+  // 8 M-cycles per block
+  assert_eq!(setup.memory.take_stall_cycles(), 4 * 32);
+
+  // Twice the CPU cycles in double-speed mode
+  setup.memory.double_speed = true;
+  setup.memory.write(0xFF55, 0x00);
+  assert_eq!(setup.memory.read(0x8840), 0x40);
+  assert_eq!(setup.memory.take_stall_cycles(), 64);
+}
+
+#[test]
+fn test_hblank_vram_dma() {
+  let mut setup = cgb_setup();
+
+  setup.memory.write(0xFF55, 0x82);
+  assert_eq!(setup.memory.read(0xFF55), 0x02);
+  assert_eq!(setup.memory.read(0x8800), 0x00);
+
+  // One block per HBlank
+  setup.memory.tick(80 + 172);
+  assert_eq!(setup.memory.read(0x880F), 0x0F);
+  assert_eq!(setup.memory.read(0x8810), 0x00);
+  assert_eq!(setup.memory.read(0xFF55), 0x01);
+  assert_eq!(setup.memory.take_stall_cycles(), 32);
+
+  setup.memory.tick(456);
+  setup.memory.tick(456);
+  assert_eq!(setup.memory.read(0x882F), 0x2F);
+  assert_eq!(setup.memory.read(0xFF55), 0xFF);
+
+  setup.memory.tick(456);
+  assert_eq!(setup.memory.read(0x8830), 0x00);
+}
+
+#[test]
+fn test_hblank_vram_dma_cancel() {
+  let mut setup = cgb_setup();
+
+  setup.memory.write(0xFF55, 0x85);
+  setup.memory.tick(80 + 172);
+  setup.memory.write(0xFF55, 0x00);
+
+  // Bit 7 set with the blocks that were left, minus one
+  assert_eq!(setup.memory.read(0xFF55), 0x84);
+
+  setup.memory.tick(456);
+  assert_eq!(setup.memory.read(0x8810), 0x00);
+}
+
+#[test]
+fn test_vram_dma_absent_on_dmg() {
+  let mut setup = Setup::new();
+
+  setup.memory.write(0xC000, 0x12);
+  setup.memory.write(0xFF51, 0xC0);
+  setup.memory.write(0xFF53, 0x08);
+  setup.memory.write(0xFF55, 0x00);
+
+  assert_eq!(setup.memory.read(0x8800), 0x00);
+  assert_eq!(setup.memory.take_stall_cycles(), 0);
+}
+
+#[test]
+fn test_wram_banks() {
+  let mut setup = Setup::new();
+  setup.memory.ppu.cgb = true;
+
+  setup.memory.write(0xC000, 0x10);
+  for bank in 1..8 {
+    setup.memory.write(0xFF70, bank);
+    setup.memory.write(0xD000, bank);
+  }
+
+  setup.memory.write(0xFF70, 3);
+  assert_eq!(setup.memory.read(0xFF70), 0xFB);
+  assert_eq!(setup.memory.read(0xD000), 3);
+  assert_eq!(setup.memory.read(0xC000), 0x10);
+
+  // Bank 0 selects bank 1
+  setup.memory.write(0xFF70, 0);
+  assert_eq!(setup.memory.read(0xD000), 1);
+
+  // Echo RAM mirrors 0xC000-0xDDFF
+  setup.memory.write(0xFF70, 7);
+  assert_eq!(setup.memory.read(0xF000), 7);
+  setup.memory.write(0xE001, 0x22);
+  assert_eq!(setup.memory.read(0xC001), 0x22);
+}
+
+#[test]
+fn test_wram_bank_fixed_on_dmg() {
+  let mut setup = Setup::new();
+
+  setup.memory.write(0xD000, 0x01);
+  setup.memory.write(0xFF70, 0x02);
+  setup.memory.write(0xD000, 0x02);
+
+  setup.memory.write(0xFF70, 0x01);
+  assert_eq!(setup.memory.read(0xD000), 0x02);
+}
+
+#[test]
+fn test_double_speed_switch() {
+  let mut setup = Setup::new();
+  let mut cpu = Cpu::new();
+  setup.memory.ppu.cgb = true;
+
+  // STOP without arming KEY1 keeps the speed, sleeping until a button
+  setup.memory.write(0x0000, 0x10);
+  setup.memory.write(0x0002, 0x10);
+  cpu.run_instruction(&mut setup.memory);
+  assert_eq!(setup.memory.read(0xFF4D), 0x7E);
+  setup.memory.write(0xFF00, 0x20);
+  setup.memory.set_button(Button::Right, true);
+
+  setup.memory.write(0xFF4D, 0x01);
+  assert_eq!(setup.memory.read(0xFF4D), 0x7F);
+  cpu.run_instruction(&mut setup.memory);
+  assert_eq!(setup.memory.read(0xFF4D), 0xFE);
+  assert_eq!(cpu.registers.pc, 0x0004);
+
+  // The LCD keeps its pace, taking twice the CPU cycles per line
+  setup.memory.write(0xFF40, 0x00);
+  setup.memory.write(0xFF40, 0x91);
+  setup.memory.tick(456);
+  assert_eq!(setup.memory.read(0xFF44), 0);
+  setup.memory.tick(456);
+  assert_eq!(setup.memory.read(0xFF44), 1);
+}
+
+#[test]
+fn test_infrared_loopback() {
+  let mut setup = Setup::new();
+  setup.memory.infrared.connect(Box::new(Loopback::new()));
+
+  // Reading disabled, then the LED seen through the mirror
+  setup.memory.write(0xFF56, 0x01);
+  assert_eq!(setup.memory.read(0xFF56), 0x3F);
+
+  setup.memory.write(0xFF56, 0xC1);
+  assert_eq!(setup.memory.read(0xFF56), 0xFD);
+
+  setup.memory.write(0xFF56, 0xC0);
+  assert_eq!(setup.memory.read(0xFF56), 0xFE);
+}
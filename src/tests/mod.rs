@@ -1,3 +1,21 @@
 #[cfg(test)]
 mod instruction_tests;
 mod registers_tests;
+#[cfg(test)]
+mod cartridge_tests;
+#[cfg(test)]
+mod ppu_tests;
+#[cfg(test)]
+mod memory_tests;
+#[cfg(test)]
+mod timer_tests;
+#[cfg(test)]
+mod joypad_tests;
+#[cfg(test)]
+mod apu_tests;
+#[cfg(test)]
+mod audio_tests;
+#[cfg(test)]
+mod gbs_tests;
+#[cfg(test)]
+mod serial_tests;
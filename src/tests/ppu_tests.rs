@@ -0,0 +1,513 @@
+use memory::memory::Memory;
+use ppu::ppu::Mode;
+use ppu::ppu::Renderer;
+use ppu::ppu::DOTS_PER_LINE;
+use ppu::ppu::SCREEN_WIDTH;
+
+struct Setup {
+  memory: Memory
+}
+
+impl Setup {
+  pub fn new() -> Self {
+    Self {
+      memory: Memory::new(),
+    }
+  }
+
+  fn interrupt_flags(&self) -> u8 {
+    self.memory.read(0xFF0F)
+  }
+}
+
+#[test]
+fn test_ppu_mode_sequence() {
+  let mut setup = Setup::new();
+
+  assert_eq!(setup.memory.read(0xFF41) & 0x03, Mode::OamScan as u8);
+
+  setup.memory.tick(80);
+  assert_eq!(setup.memory.ppu.mode, Mode::PixelTransfer);
+
+  setup.memory.tick(172);
+  assert_eq!(setup.memory.ppu.mode, Mode::HBlank);
+
+  setup.memory.tick(204);
+  assert_eq!(setup.memory.ppu.mode, Mode::OamScan);
+  assert_eq!(setup.memory.read(0xFF44), 1);
+}
+
+#[test]
+fn test_ppu_vblank_interrupt() {
+  let mut setup = Setup::new();
+
+  setup.memory.tick(DOTS_PER_LINE as u64 * 144 - 1);
+  assert_eq!(setup.interrupt_flags() & 0x01, 0x00);
+
+  setup.memory.tick(1);
+  assert_eq!(setup.memory.read(0xFF44), 144);
+  assert_eq!(setup.memory.ppu.mode, Mode::VBlank);
+  assert_eq!(setup.interrupt_flags() & 0x01, 0x01);
+
+  // A whole frame brings LY back to 0
+  setup.memory.tick(DOTS_PER_LINE as u64 * 10);
+  assert_eq!(setup.memory.read(0xFF44), 0);
+}
+
+#[test]
+fn test_ppu_lyc_stat_interrupt() {
+  let mut setup = Setup::new();
+
+  setup.memory.write(0xFF45, 3);
+  setup.memory.write(0xFF41, 0x40);
+
+  setup.memory.tick(DOTS_PER_LINE as u64 * 3 - 1);
+  assert_eq!(setup.interrupt_flags() & 0x02, 0x00);
+  assert_eq!(setup.memory.read(0xFF41) & 0x04, 0x00);
+
+  setup.memory.tick(1);
+  assert_eq!(setup.interrupt_flags() & 0x02, 0x02);
+  assert_eq!(setup.memory.read(0xFF41) & 0x04, 0x04);
+}
+
+#[test]
+fn test_ppu_lcd_off_resets_ly() {
+  let mut setup = Setup::new();
+
+  setup.memory.tick(DOTS_PER_LINE as u64 * 5);
+  setup.memory.write(0xFF40, 0x11);
+
+  assert_eq!(setup.memory.read(0xFF44), 0);
+  assert_eq!(setup.memory.read(0xFF41) & 0x03, 0);
+
+  setup.memory.tick(DOTS_PER_LINE as u64);
+  assert_eq!(setup.memory.read(0xFF44), 0);
+}
+
+// Fills tile `tile` in the block at `base` with colour index `colour`
+fn fill_tile(memory: &mut Memory, base: u16, tile: i16, colour: u8) {
+  let address = (base as i32 + tile as i32 * 16) as u16;
+  let low = if colour & 0x01 != 0 { 0xFF } else { 0x00 };
+  let high = if colour & 0x02 != 0 { 0xFF } else { 0x00 };
+
+  for row in 0..8 {
+    memory.write(address + row * 2, low);
+    memory.write(address + row * 2 + 1, high);
+  }
+}
+
+fn run_frame(memory: &mut Memory) {
+  memory.tick(DOTS_PER_LINE as u64 * 154);
+}
+
+fn pixel(memory: &Memory, x: usize, y: usize) -> u32 {
+  memory.ppu.framebuffer()[y * SCREEN_WIDTH + x]
+}
+
+#[test]
+fn test_background_unsigned_tile_data() {
+  let mut setup = Setup::new();
+
+  // Tile 1 is black, placed at map position (1, 0)
+  fill_tile(&mut setup.memory, 0x8000, 1, 3);
+  setup.memory.write(0x9801, 0x01);
+  setup.memory.write(0xFF47, 0xE4);
+
+  run_frame(&mut setup.memory);
+  assert_eq!(pixel(&setup.memory, 7, 0), 0xFFFFFF);
+  assert_eq!(pixel(&setup.memory, 8, 0), 0x000000);
+
+  // Scrolling moves it left
+  setup.memory.write(0xFF43, 4);
+  run_frame(&mut setup.memory);
+  assert_eq!(pixel(&setup.memory, 4, 0), 0x000000);
+}
+
+#[test]
+fn test_background_signed_tile_data() {
+  let mut setup = Setup::new();
+
+  // With LCDC bit 4 clear, indices 0x00-0x7F count up from 0x9000
+  fill_tile(&mut setup.memory, 0x9000, 1, 2);
+  fill_tile(&mut setup.memory, 0x8000, 1, 1);
+  setup.memory.write(0x9800, 0x01);
+  setup.memory.write(0xFF47, 0xE4);
+  setup.memory.write(0xFF40, 0x81);
+
+  run_frame(&mut setup.memory);
+  assert_eq!(pixel(&setup.memory, 0, 0), 0x555555);
+}
+
+#[test]
+fn test_window_line_counter() {
+  let mut setup = Setup::new();
+
+  // Window map at 0x9C00: first row of tiles white, second row black
+  fill_tile(&mut setup.memory, 0x8000, 1, 3);
+  for column in 0..32 {
+    setup.memory.write(0x9C20 + column, 0x01);
+  }
+  setup.memory.write(0xFF47, 0xE4);
+  setup.memory.write(0xFF4A, 0);
+  setup.memory.write(0xFF4B, 7);
+  setup.memory.write(0xFF40, 0xF1);
+
+  // Hide the window for lines 8-15 halfway through the frame
+  setup.memory.tick(DOTS_PER_LINE as u64 * 8);
+  setup.memory.write(0xFF40, 0xD1);
+  setup.memory.tick(DOTS_PER_LINE as u64 * 8);
+  setup.memory.write(0xFF40, 0xF1);
+  setup.memory.tick(DOTS_PER_LINE as u64 * 138);
+
+  // The window resumes on its own line 8, not on screen line 16
+  assert_eq!(pixel(&setup.memory, 0, 7), 0xFFFFFF);
+  assert_eq!(pixel(&setup.memory, 0, 16), 0x000000);
+}
+
+fn write_sprite(memory: &mut Memory, index: u16, y: u8, x: u8, tile: u8, attributes: u8) {
+  let address = 0xFE00 + index * 4;
+  memory.write(address, y);
+  memory.write(address + 1, x);
+  memory.write(address + 2, tile);
+  memory.write(address + 3, attributes);
+}
+
+#[test]
+fn test_sprite_priority_by_x_then_index() {
+  let mut setup = Setup::new();
+
+  fill_tile(&mut setup.memory, 0x8000, 1, 1);
+  fill_tile(&mut setup.memory, 0x8000, 2, 3);
+  setup.memory.write(0xFF47, 0xE4);
+  setup.memory.write(0xFF48, 0xE4);
+  setup.memory.write(0xFF40, 0x93);
+
+  // Sprite 0 at x=4 overlaps sprite 1 at x=0, which has the lower X
+  write_sprite(&mut setup.memory, 0, 16, 12, 2, 0x00);
+  write_sprite(&mut setup.memory, 1, 16, 8, 1, 0x00);
+  // Same X: the lower OAM index wins
+  write_sprite(&mut setup.memory, 2, 32, 40, 2, 0x00);
+  write_sprite(&mut setup.memory, 3, 32, 40, 1, 0x00);
+
+  run_frame(&mut setup.memory);
+
+  assert_eq!(pixel(&setup.memory, 5, 0), 0xAAAAAA);
+  assert_eq!(pixel(&setup.memory, 9, 0), 0x000000);
+  assert_eq!(pixel(&setup.memory, 32, 16), 0x000000);
+}
+
+#[test]
+fn test_sprite_limit_per_line() {
+  let mut setup = Setup::new();
+
+  fill_tile(&mut setup.memory, 0x8000, 1, 3);
+  setup.memory.write(0xFF48, 0xE4);
+  setup.memory.write(0xFF40, 0x93);
+
+  for index in 0..11 {
+    write_sprite(&mut setup.memory, index, 16, 8 + index as u8 * 8, 1, 0x00);
+  }
+
+  run_frame(&mut setup.memory);
+
+  assert_eq!(pixel(&setup.memory, 72, 0), 0x000000);
+  assert_eq!(pixel(&setup.memory, 80, 0), 0xFFFFFF);
+}
+
+#[test]
+fn test_sprite_flip_and_tall_sprites() {
+  let mut setup = Setup::new();
+
+  // Tile 4 is white, tile 5 black; only the leftmost column of tile 6 is set
+  fill_tile(&mut setup.memory, 0x8000, 5, 3);
+  for row in 0..8 {
+    setup.memory.write(0x8060 + row * 2, 0x80);
+    setup.memory.write(0x8060 + row * 2 + 1, 0x80);
+  }
+  setup.memory.write(0xFF48, 0xE4);
+  setup.memory.write(0xFF40, 0x97);
+
+  // 8x16 with Y flip: tile 5 ends up on top
+  write_sprite(&mut setup.memory, 0, 16, 8, 4, 0x40);
+  // X flip moves the set column to the right edge
+  write_sprite(&mut setup.memory, 1, 48, 16, 6, 0x20);
+
+  run_frame(&mut setup.memory);
+
+  assert_eq!(pixel(&setup.memory, 0, 0), 0x000000);
+  assert_eq!(pixel(&setup.memory, 0, 8), 0xFFFFFF);
+  assert_eq!(pixel(&setup.memory, 8, 32), 0xFFFFFF);
+  assert_eq!(pixel(&setup.memory, 15, 32), 0x000000);
+}
+
+#[test]
+fn test_sprite_behind_background() {
+  let mut setup = Setup::new();
+
+  // Background colour 1 on the left tile, colour 0 on the right one
+  fill_tile(&mut setup.memory, 0x8000, 1, 1);
+  fill_tile(&mut setup.memory, 0x8000, 2, 3);
+  setup.memory.write(0x9800, 0x01);
+  setup.memory.write(0xFF47, 0xE4);
+  setup.memory.write(0xFF48, 0xE4);
+  setup.memory.write(0xFF40, 0x93);
+
+  write_sprite(&mut setup.memory, 0, 16, 12, 2, 0x80);
+
+  run_frame(&mut setup.memory);
+
+  assert_eq!(pixel(&setup.memory, 6, 0), 0xAAAAAA);
+  assert_eq!(pixel(&setup.memory, 9, 0), 0x000000);
+}
+
+fn fifo_setup() -> Setup {
+  let mut setup = Setup::new();
+  setup.memory.ppu.renderer = Renderer::Fifo;
+  setup
+}
+
+// Dots spent in mode 3 on the next line, starting from the top of a line
+fn mode_3_length(memory: &mut Memory) -> u32 {
+  memory.tick(80);
+  assert_eq!(memory.ppu.mode, Mode::PixelTransfer);
+
+  let mut dots = 0;
+  while memory.ppu.mode == Mode::PixelTransfer {
+    memory.tick(1);
+    dots += 1;
+  }
+
+  memory.tick(DOTS_PER_LINE as u64 - 80 - dots as u64);
+  dots
+}
+
+#[test]
+fn test_fifo_mode_3_length() {
+  let mut setup = fifo_setup();
+  assert_eq!(mode_3_length(&mut setup.memory), 172);
+
+  // The SCX % 8 pixels discarded from the first tile lengthen mode 3
+  setup.memory.write(0xFF43, 3);
+  assert_eq!(mode_3_length(&mut setup.memory), 175);
+  setup.memory.write(0xFF43, 0);
+
+  // Each sprite fetch stalls the FIFO for 6 to 11 dots
+  setup.memory.write(0xFF40, 0x93);
+  write_sprite(&mut setup.memory, 0, 18, 8, 0, 0x00);
+  let length = mode_3_length(&mut setup.memory);
+  assert!((178..=183).contains(&length), "mode 3 took {} dots", length);
+
+  // And HBlank shrinks to keep lines at 456 dots
+  assert_eq!(setup.memory.read(0xFF44), 3);
+}
+
+#[test]
+fn test_fifo_matches_scanline_renderer() {
+  let mut scanline = Setup::new();
+  let mut fifo = fifo_setup();
+
+  for setup in [&mut scanline, &mut fifo].iter_mut() {
+    let memory = &mut setup.memory;
+
+    fill_tile(memory, 0x8000, 1, 1);
+    fill_tile(memory, 0x8000, 2, 2);
+    fill_tile(memory, 0x8000, 3, 3);
+    for offset in 0..0x400 {
+      memory.write(0x9800 + offset, (offset % 3) as u8 + 1);
+      memory.write(0x9C00 + offset, 3 - (offset % 3) as u8);
+    }
+
+    memory.write(0xFF42, 5);
+    memory.write(0xFF43, 13);
+    memory.write(0xFF47, 0xE4);
+    memory.write(0xFF48, 0xE4);
+    memory.write(0xFF49, 0x1B);
+    memory.write(0xFF4A, 40);
+    memory.write(0xFF4B, 87);
+    memory.write(0xFF40, 0xF3);
+
+    write_sprite(memory, 0, 20, 4, 3, 0x00);
+    write_sprite(memory, 1, 20, 8, 1, 0x10);
+    write_sprite(memory, 2, 60, 90, 2, 0x80);
+    write_sprite(memory, 3, 100, 160, 1, 0x20);
+
+    run_frame(memory);
+  }
+
+  assert!(scanline.memory.ppu.framebuffer() == fifo.memory.ppu.framebuffer());
+}
+
+#[test]
+fn test_fifo_window_at_left_edge() {
+  for &wx in [7, 3, 0].iter() {
+    let mut scanline = Setup::new();
+    let mut fifo = fifo_setup();
+
+    for setup in [&mut scanline, &mut fifo].iter_mut() {
+      let memory = &mut setup.memory;
+
+      fill_tile(memory, 0x8000, 1, 1);
+      fill_tile(memory, 0x8000, 2, 2);
+      fill_tile(memory, 0x8000, 3, 3);
+      for offset in 0..0x400 {
+        memory.write(0x9C00 + offset, (offset % 3) as u8 + 1);
+      }
+
+      // A pending SCX % 8 discard must not eat into the window
+      memory.write(0xFF43, 13);
+      memory.write(0xFF47, 0xE4);
+      memory.write(0xFF4A, 0);
+      memory.write(0xFF4B, wx);
+      memory.write(0xFF40, 0xF1);
+
+      run_frame(memory);
+    }
+
+    assert!(scanline.memory.ppu.framebuffer() == fifo.memory.ppu.framebuffer(), "WX = {}", wx);
+  }
+}
+
+#[test]
+fn test_fifo_mid_line_palette_write() {
+  let mut setup = fifo_setup();
+
+  fill_tile(&mut setup.memory, 0x8000, 0, 3);
+  setup.memory.write(0xFF47, 0xFF);
+
+  // Switch the background to white halfway through line 0's mode 3
+  setup.memory.tick(80 + 90);
+  setup.memory.write(0xFF47, 0x00);
+  setup.memory.tick(DOTS_PER_LINE as u64 - 80 - 90);
+
+  assert_eq!(pixel(&setup.memory, 0, 0), 0x000000);
+  assert_eq!(pixel(&setup.memory, 159, 0), 0xFFFFFF);
+}
+
+fn cgb_setup() -> Setup {
+  let mut setup = Setup::new();
+  setup.memory.ppu.cgb = true;
+  setup
+}
+
+// Writes an RGB555 colour through BCPD or OCPD with auto-increment
+fn write_palette(memory: &mut Memory, selector: u16, palette: u8, colours: &[u16]) {
+  memory.write(selector, 0x80 | (palette * 8));
+  for colour in colours.iter() {
+    memory.write(selector + 1, *colour as u8);
+    memory.write(selector + 1, (*colour >> 8) as u8);
+  }
+}
+
+#[test]
+fn test_cgb_palette_auto_increment() {
+  let mut setup = cgb_setup();
+
+  write_palette(&mut setup.memory, 0xFF68, 7, &[0x001F, 0x03E0, 0x7C00, 0x7FFF]);
+  assert_eq!(setup.memory.read(0xFF68), 0xC0);
+
+  setup.memory.write(0xFF68, 0x3D);
+  assert_eq!(setup.memory.read(0xFF69), 0x7C);
+
+  // Without bit 7 the index stays put
+  setup.memory.write(0xFF6A, 0x05);
+  setup.memory.write(0xFF6B, 0x12);
+  setup.memory.write(0xFF6B, 0x34);
+  assert_eq!(setup.memory.read(0xFF6A), 0x45);
+  assert_eq!(setup.memory.read(0xFF6B), 0x34);
+}
+
+#[test]
+fn test_cgb_registers_absent_on_dmg() {
+  let mut setup = Setup::new();
+
+  setup.memory.write(0xFF4F, 0x01);
+  setup.memory.write(0x8000, 0x12);
+  assert_eq!(setup.memory.read(0xFF4F), 0xFF);
+  assert_eq!(setup.memory.read(0xFF69), 0xFF);
+  assert_eq!(setup.memory.read(0x8000), 0x12);
+}
+
+#[test]
+fn test_cgb_vram_banks() {
+  let mut setup = cgb_setup();
+
+  setup.memory.write(0x8000, 0x11);
+  setup.memory.write(0xFF4F, 0x01);
+  assert_eq!(setup.memory.read(0xFF4F), 0xFF);
+  assert_eq!(setup.memory.read(0x8000), 0x00);
+
+  setup.memory.write(0x8000, 0x22);
+  setup.memory.write(0xFF4F, 0x00);
+  assert_eq!(setup.memory.read(0xFF4F), 0xFE);
+  assert_eq!(setup.memory.read(0x8000), 0x11);
+}
+
+// Tile 1 in bank 1 has only its top-left pixel set, to colour 3
+fn cgb_scene(memory: &mut Memory) {
+  write_palette(memory, 0xFF68, 0, &[0x7FFF, 0x0000, 0x0000, 0x0000]);
+  write_palette(memory, 0xFF68, 2, &[0x0000, 0x0000, 0x0000, 0x001F]);
+  write_palette(memory, 0xFF6A, 3, &[0x0000, 0x03E0, 0x03E0, 0x03E0]);
+
+  memory.write(0xFF4F, 0x01);
+  memory.write(0x8010, 0x80);
+  memory.write(0x8011, 0x80);
+  fill_tile(memory, 0x8000, 2, 1);
+  // Map entries (0, 0) and (1, 0): tile 1 from bank 1 with palette 2, the
+  // second one flipped both ways
+  memory.write(0x9800, 0x0A);
+  memory.write(0x9801, 0x6A);
+  memory.write(0xFF4F, 0x00);
+  memory.write(0x9800, 0x01);
+  memory.write(0x9801, 0x01);
+}
+
+#[test]
+fn test_cgb_background_attributes() {
+  let mut setup = cgb_setup();
+  cgb_scene(&mut setup.memory);
+
+  run_frame(&mut setup.memory);
+
+  assert_eq!(pixel(&setup.memory, 0, 0), 0xFF0000);
+  assert_eq!(pixel(&setup.memory, 1, 0), 0x000000);
+  assert_eq!(pixel(&setup.memory, 15, 7), 0xFF0000);
+  assert_eq!(pixel(&setup.memory, 8, 0), 0x000000);
+  // Tiles left at index 0 use palette 0, colour 0 white
+  assert_eq!(pixel(&setup.memory, 20, 0), 0xFFFFFF);
+}
+
+#[test]
+fn test_cgb_sprite_priority() {
+  for renderer in [Renderer::Scanline, Renderer::Fifo].iter() {
+    let mut setup = cgb_setup();
+    setup.memory.ppu.renderer = *renderer;
+    cgb_scene(&mut setup.memory);
+
+    // Green sprites from bank 1 tile 2 with OBJ palette 3 over both tiles,
+    // the map priority bit set on the first one
+    setup.memory.write(0xFF4F, 0x01);
+    setup.memory.write(0x9800, 0x8A);
+    setup.memory.write(0xFF4F, 0x00);
+    write_sprite(&mut setup.memory, 0, 16, 8, 2, 0x0B);
+    write_sprite(&mut setup.memory, 1, 16, 16, 2, 0x0B);
+    // On CGB the lower OAM index wins even with a higher X
+    write_sprite(&mut setup.memory, 2, 32, 12, 2, 0x08);
+    write_sprite(&mut setup.memory, 3, 32, 8, 2, 0x0B);
+    write_sprite(&mut setup.memory, 4, 32, 4, 2, 0x0B);
+    setup.memory.write(0xFF40, 0x93);
+
+    run_frame(&mut setup.memory);
+
+    // BG priority only applies over non-zero background colours
+    assert_eq!(pixel(&setup.memory, 0, 0), 0xFF0000);
+    assert_eq!(pixel(&setup.memory, 1, 0), 0x00FF00);
+    assert_eq!(pixel(&setup.memory, 15, 7), 0x00FF00);
+    assert_eq!(pixel(&setup.memory, 3, 16), 0x00FF00);
+    assert_eq!(pixel(&setup.memory, 4, 16), 0xFFFFFF);
+
+    // LCDC bit 0 clear puts every sprite on top
+    setup.memory.write(0xFF40, 0x92);
+    run_frame(&mut setup.memory);
+    assert_eq!(pixel(&setup.memory, 0, 0), 0x00FF00);
+  }
+}
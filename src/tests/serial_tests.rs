@@ -0,0 +1,190 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::Result;
+use std::io::Write;
+use std::rc::Rc;
+
+use memory::memory::Memory;
+use serial::link::link_cable;
+use serial::printer::GameBoyPrinter;
+use serial::serial::SerialDevice;
+use serial::serial::SerialLogger;
+
+const SB_ADDRESS: u16 = 0xFF01;
+const SC_ADDRESS: u16 = 0xFF02;
+const IF_ADDRESS: u16 = 0xFF0F;
+
+// A log the test can read back after handing it to the serial port
+#[derive(Clone)]
+struct SharedLog(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedLog {
+  fn write(&mut self, data: &[u8]) -> Result<usize> {
+    self.0.borrow_mut().extend_from_slice(data);
+    Ok(data.len())
+  }
+
+  fn flush(&mut self) -> Result<()> {
+    Ok(())
+  }
+}
+
+fn send(memory: &mut Memory, value: u8) {
+  memory.write(SB_ADDRESS, value);
+  memory.write(SC_ADDRESS, 0x81);
+  memory.tick(512 * 8);
+}
+
+#[test]
+fn test_internal_clock_transfer() {
+  let mut memory = Memory::new();
+  memory.write(SB_ADDRESS, 0x55);
+  memory.write(SC_ADDRESS, 0x81);
+  assert_eq!(memory.read(SC_ADDRESS), 0xFF);
+
+  // Nothing connected shifts in ones, at 8192 Hz
+  memory.tick(512 * 3);
+  assert_eq!(memory.read(SB_ADDRESS), 0xAF);
+
+  memory.tick(512 * 5 - 4);
+  assert_eq!(memory.read(SC_ADDRESS), 0xFF);
+  assert_eq!(memory.read(IF_ADDRESS) & 0x08, 0);
+
+  memory.tick(4);
+  assert_eq!(memory.read(SB_ADDRESS), 0xFF);
+  assert_eq!(memory.read(SC_ADDRESS), 0x7F);
+  assert_eq!(memory.read(IF_ADDRESS) & 0x08, 0x08);
+}
+
+#[test]
+fn test_cgb_fast_clock() {
+  let mut memory = Memory::new();
+  memory.serial.cgb = true;
+  memory.write(SC_ADDRESS, 0x83);
+  assert_eq!(memory.read(SC_ADDRESS), 0xFF);
+
+  memory.tick(16 * 8);
+  assert_eq!(memory.read(SC_ADDRESS), 0x7F);
+  assert_eq!(memory.read(IF_ADDRESS) & 0x08, 0x08);
+
+  // DMG has no fast clock
+  let mut memory = Memory::new();
+  memory.write(SC_ADDRESS, 0x83);
+  assert_eq!(memory.read(SC_ADDRESS), 0xFF);
+
+  memory.tick(16 * 8);
+  assert_eq!(memory.read(SC_ADDRESS), 0xFF);
+}
+
+#[test]
+fn test_external_clock_waits() {
+  let mut memory = Memory::new();
+  memory.write(SB_ADDRESS, 0x42);
+  memory.write(SC_ADDRESS, 0x80);
+  memory.tick(512 * 16);
+
+  assert_eq!(memory.read(SB_ADDRESS), 0x42);
+  assert_eq!(memory.read(SC_ADDRESS), 0xFE);
+  assert_eq!(memory.read(IF_ADDRESS) & 0x08, 0);
+}
+
+#[test]
+fn test_logger_writes_bytes_sent() {
+  let log = SharedLog(Rc::new(RefCell::new(Vec::new())));
+  let mut memory = Memory::new();
+  memory.serial.connect(Box::new(SerialLogger::new(log.clone())));
+
+  for &byte in b"Passed\n" {
+    send(&mut memory, byte);
+  }
+
+  assert_eq!(&log.0.borrow()[..], b"Passed\n");
+}
+
+#[test]
+fn test_linked_game_boys_swap_bytes() {
+  let (first, second) = link_cable();
+  let mut leader = Memory::new();
+  let mut follower = Memory::new();
+  leader.serial.connect(Box::new(first));
+  follower.serial.connect(Box::new(second));
+
+  follower.write(SB_ADDRESS, 0x12);
+  follower.write(SC_ADDRESS, 0x80);
+  leader.write(SB_ADDRESS, 0xA7);
+  leader.write(SC_ADDRESS, 0x81);
+
+  for _ in 0..512 * 8 / 4 {
+    follower.tick(4);
+    leader.tick(4);
+  }
+  follower.tick(4);
+
+  assert_eq!(leader.read(SB_ADDRESS), 0x12);
+  assert_eq!(follower.read(SB_ADDRESS), 0xA7);
+  assert_eq!(leader.read(IF_ADDRESS) & 0x08, 0x08);
+  assert_eq!(follower.read(IF_ADDRESS) & 0x08, 0x08);
+}
+
+// Clocks a byte into a device, returning the byte it sent back
+fn exchange_byte(device: &mut dyn SerialDevice, byte: u8) -> u8 {
+  (0..8).fold(0, |response, bit| {
+    (response << 1) | device.exchange_bit(byte & (0x80 >> bit) != 0) as u8
+  })
+}
+
+// Sends a packet, returning the printer's answers to the two bytes after it
+fn send_packet(printer: &mut GameBoyPrinter, command: u8, compressed: bool, data: &[u8]) -> (u8, u8) {
+  let mut packet = vec![command, compressed as u8, data.len() as u8, (data.len() >> 8) as u8];
+  packet.extend_from_slice(data);
+  let checksum = packet.iter().fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+
+  for &byte in [0x88, 0x33].iter().chain(packet.iter()).chain(checksum.to_le_bytes().iter()) {
+    assert_eq!(exchange_byte(printer, byte), 0x00);
+  }
+
+  (exchange_byte(printer, 0x00), exchange_byte(printer, 0x00))
+}
+
+#[test]
+fn test_printer_prints_image() {
+  let directory = std::env::temp_dir().join("clonelebi-printer-test");
+  let _ = fs::remove_dir_all(&directory);
+  fs::create_dir_all(&directory).unwrap();
+  let mut printer = GameBoyPrinter::new(&directory);
+
+  assert_eq!(send_packet(&mut printer, 0x01, false, &[]), (0x81, 0x00));
+
+  // Two tile rows of colour 3 in the first tile and colour 0 everywhere else,
+  // run-length encoded
+  let mut data = vec![0x0F];
+  data.extend_from_slice(&[0xFF; 16]);
+  data.extend_from_slice(&[0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xEA, 0x00]);
+
+  assert_eq!(send_packet(&mut printer, 0x04, true, &data), (0x81, 0x08));
+  assert_eq!(send_packet(&mut printer, 0x04, false, &[]), (0x81, 0x08));
+  assert_eq!(send_packet(&mut printer, 0x02, false, &[0x01, 0x13, 0xE4, 0x40]), (0x81, 0x00));
+
+  let decoder = png::Decoder::new(fs::File::open(directory.join("print-1.png")).unwrap());
+  let mut reader = decoder.read_info().unwrap();
+  let mut pixels = vec![0; reader.output_buffer_size()];
+  let info = reader.next_frame(&mut pixels).unwrap();
+
+  assert_eq!((info.width, info.height), (160, 16));
+  assert_eq!(pixels[0], 0x00);
+  assert_eq!(pixels[7 * 160 + 7], 0x00);
+  assert_eq!(pixels[8], 0xFF);
+  assert_eq!(pixels[15 * 160 + 159], 0xFF);
+}
+
+#[test]
+fn test_printer_checksum_error() {
+  let mut printer = GameBoyPrinter::new(&std::env::temp_dir());
+
+  for &byte in &[0x88, 0x33, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00] {
+    exchange_byte(&mut printer, byte);
+  }
+
+  assert_eq!(exchange_byte(&mut printer, 0x00), 0x81);
+  assert_eq!(exchange_byte(&mut printer, 0x00), 0x01);
+}
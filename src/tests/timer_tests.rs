@@ -0,0 +1,128 @@
+use memory::memory::Memory;
+
+struct Setup {
+  memory: Memory
+}
+
+impl Setup {
+  pub fn new() -> Self {
+    Self {
+      memory: Memory::new(),
+    }
+  }
+
+  // TIMA at 262144 Hz, one increment every 16 T-cycles
+  fn fast_timer(tima: u8, tma: u8) -> Self {
+    let mut setup = Self::new();
+    setup.memory.write(0xFF06, tma);
+    setup.memory.write(0xFF05, tima);
+    setup.memory.write(0xFF07, 0x05);
+    setup
+  }
+
+  fn timer_interrupt(&self) -> bool {
+    self.memory.read(0xFF0F) & 0x04 != 0
+  }
+}
+
+#[test]
+fn test_div_counts_and_resets() {
+  let mut setup = Setup::new();
+
+  setup.memory.tick(255 * 4);
+  assert_eq!(setup.memory.read(0xFF04), 3);
+
+  setup.memory.write(0xFF04, 0x42);
+  assert_eq!(setup.memory.read(0xFF04), 0);
+  setup.memory.tick(256);
+  assert_eq!(setup.memory.read(0xFF04), 1);
+}
+
+#[test]
+fn test_tima_clock_selects() {
+  for &(tac, period) in [(0x04, 1024), (0x05, 16), (0x06, 64), (0x07, 256)].iter() {
+    let mut setup = Setup::new();
+    setup.memory.write(0xFF07, tac);
+
+    setup.memory.tick(period - 4);
+    assert_eq!(setup.memory.read(0xFF05), 0);
+    setup.memory.tick(4);
+    assert_eq!(setup.memory.read(0xFF05), 1);
+  }
+
+  // Disabled timers stand still
+  let mut setup = Setup::new();
+  setup.memory.write(0xFF07, 0x01);
+  setup.memory.tick(1024);
+  assert_eq!(setup.memory.read(0xFF05), 0);
+  assert_eq!(setup.memory.read(0xFF07), 0xF9);
+}
+
+#[test]
+fn test_tima_overflow_reloads_one_m_cycle_late() {
+  let mut setup = Setup::fast_timer(0xFF, 0x80);
+
+  setup.memory.tick(16);
+  assert_eq!(setup.memory.read(0xFF05), 0x00);
+  assert!(!setup.timer_interrupt());
+
+  setup.memory.tick(4);
+  assert_eq!(setup.memory.read(0xFF05), 0x80);
+  assert!(setup.timer_interrupt());
+}
+
+#[test]
+fn test_tima_write_cancels_pending_reload() {
+  let mut setup = Setup::fast_timer(0xFF, 0x80);
+
+  setup.memory.tick(16);
+  setup.memory.write(0xFF05, 0x10);
+  setup.memory.tick(4);
+
+  assert_eq!(setup.memory.read(0xFF05), 0x10);
+  assert!(!setup.timer_interrupt());
+}
+
+#[test]
+fn test_writes_during_reload_cycle() {
+  let mut setup = Setup::fast_timer(0xFF, 0x80);
+
+  setup.memory.tick(20);
+
+  // TIMA writes are lost, TMA writes land in TIMA as well
+  setup.memory.write(0xFF05, 0x10);
+  assert_eq!(setup.memory.read(0xFF05), 0x80);
+  setup.memory.write(0xFF06, 0x33);
+  assert_eq!(setup.memory.read(0xFF05), 0x33);
+
+  // One M-cycle later TIMA is writable again
+  setup.memory.tick(4);
+  setup.memory.write(0xFF05, 0x10);
+  assert_eq!(setup.memory.read(0xFF05), 0x10);
+}
+
+#[test]
+fn test_falling_edge_quirks() {
+  // Resetting DIV while the watched bit is high ticks TIMA
+  let mut setup = Setup::fast_timer(0x00, 0x00);
+  setup.memory.tick(8);
+  setup.memory.write(0xFF04, 0x00);
+  assert_eq!(setup.memory.read(0xFF05), 1);
+
+  // While it is low nothing happens
+  setup.memory.tick(4);
+  setup.memory.write(0xFF04, 0x00);
+  assert_eq!(setup.memory.read(0xFF05), 1);
+
+  // Disabling the timer while the bit is high ticks it too
+  setup.memory.tick(8);
+  setup.memory.write(0xFF07, 0x01);
+  assert_eq!(setup.memory.read(0xFF05), 2);
+
+  // As does switching to a clock whose bit is low
+  setup.memory.write(0xFF04, 0x00);
+  setup.memory.write(0xFF07, 0x05);
+  setup.memory.tick(8);
+  setup.memory.write(0xFF07, 0x04);
+  assert_eq!(setup.memory.read(0xFF05), 3);
+}
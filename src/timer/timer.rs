@@ -0,0 +1,159 @@
+// DIV, TIMA, TMA and TAC at 0xFF04-0xFF07. DIV is the top byte of a 16-bit
+// counter advancing every T-cycle. TIMA counts falling edges of one of its
+// bits, ANDed with the TAC enable, so resetting DIV or changing TAC can tick
+// TIMA too. The APU frame sequencer follows the falling edges of another bit.
+
+pub const TIMER_INTERRUPT: u8 = 0x04;
+
+// Counter bit whose falling edge steps the APU frame sequencer at 512 Hz.
+// The bit above it is used in double-speed mode, where the counter runs at
+// twice the rate.
+const FRAME_SEQUENCER_BIT: u16 = 12;
+
+// Counter bit watched for each TAC clock select: 4096, 262144, 65536 and
+// 16384 Hz
+const CLOCK_BITS: [u16; 4] = [9, 3, 5, 7];
+
+// TIMA overflow takes two M-cycles to settle: it reads 0x00 for one, where
+// a write cancels the reload, then loads TMA and requests the interrupt on
+// the next, where TIMA writes are ignored and TMA writes go through to TIMA
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Reload {
+  None,
+  Overflowed,
+  Reloading,
+}
+
+pub struct Timer {
+  pub divider: u16,
+  pub tima: u8,
+  pub tma: u8,
+  pub tac: u8,
+  reload: Reload,
+  cycles: u64,
+  pub double_speed: bool,
+  frame_clocks: u32, // Frame sequencer steps due since the last take
+}
+
+impl Timer {
+  pub fn new() -> Self {
+    Self {
+      divider: 0,
+      tima: 0,
+      tma: 0,
+      tac: 0,
+      reload: Reload::None,
+      cycles: 0,
+      double_speed: false,
+      frame_clocks: 0,
+    }
+  }
+
+  pub fn read(&self, address: u16) -> u8 {
+    match address {
+      0xFF04 => (self.divider >> 8) as u8,
+      0xFF05 => self.tima,
+      0xFF06 => self.tma,
+      0xFF07 => 0xF8 | self.tac,
+      _ => 0xFF,
+    }
+  }
+
+  pub fn write(&mut self, address: u16, value: u8) {
+    match address {
+      0xFF04 => self.set_divider(0),
+      0xFF05 => match self.reload {
+        Reload::Overflowed => {
+          self.tima = value;
+          self.reload = Reload::None;
+        },
+        Reload::Reloading => {},
+        Reload::None => self.tima = value,
+      },
+      0xFF06 => {
+        self.tma = value;
+
+        if self.reload == Reload::Reloading {
+          self.tima = value;
+        }
+      },
+      0xFF07 => {
+        let signal = self.signal();
+        self.tac = value & 0x07;
+        self.detect_falling_edge(signal);
+      },
+      _ => {},
+    }
+  }
+
+  // Advances by T-cycles, returning the interrupts to request
+  pub fn tick(&mut self, cycles: u64) -> u8 {
+    let mut interrupts = 0;
+
+    self.cycles += cycles;
+    while self.cycles >= 4 {
+      self.cycles -= 4;
+      interrupts |= self.step();
+    }
+
+    interrupts
+  }
+
+  fn step(&mut self) -> u8 {
+    let mut interrupts = 0;
+
+    match self.reload {
+      Reload::Overflowed => {
+        self.tima = self.tma;
+        self.reload = Reload::Reloading;
+        interrupts |= TIMER_INTERRUPT;
+      },
+      Reload::Reloading => self.reload = Reload::None,
+      Reload::None => {},
+    }
+
+    self.set_divider(self.divider.wrapping_add(4));
+
+    interrupts
+  }
+
+  // Frame sequencer steps to run since the last call
+  pub fn take_frame_clocks(&mut self) -> u32 {
+    std::mem::replace(&mut self.frame_clocks, 0)
+  }
+
+  fn set_divider(&mut self, divider: u16) {
+    let signal = self.signal();
+    let frame_bit = self.frame_bit();
+
+    self.divider = divider;
+    self.detect_falling_edge(signal);
+
+    if frame_bit && !self.frame_bit() {
+      self.frame_clocks += 1;
+    }
+  }
+
+  fn frame_bit(&self) -> bool {
+    let bit = FRAME_SEQUENCER_BIT + self.double_speed as u16;
+    self.divider & (1 << bit) != 0
+  }
+
+  fn signal(&self) -> bool {
+    let bit = CLOCK_BITS[(self.tac & 0x03) as usize];
+    self.tac & 0x04 != 0 && self.divider & (1 << bit) != 0
+  }
+
+  fn detect_falling_edge(&mut self, previous: bool) {
+    if !previous || self.signal() {
+      return;
+    }
+
+    let (tima, overflowed) = self.tima.overflowing_add(1);
+    self.tima = tima;
+
+    if overflowed {
+      self.reload = Reload::Overflowed;
+    }
+  }
+}